@@ -0,0 +1,53 @@
+// tabs for the pages of a draw.io diagram, installed by mdbook-drawio.
+// without this script the pages are shown one below the other.
+(function () {
+    "use strict";
+
+    function setup(container) {
+        var tabs = Array.prototype.filter.call(container.children, function (c) {
+            return c.classList.contains("drawio-tab");
+        });
+        if (tabs.length === 0) {
+            return;
+        }
+
+        var strip = document.createElement("div");
+        strip.className = "drawio-tabs-strip";
+        strip.setAttribute("role", "tablist");
+        var buttons = tabs.map(function (tab, i) {
+            var b = document.createElement("button");
+            b.type = "button";
+            b.textContent = tab.getAttribute("data-page");
+            b.setAttribute("role", "tab");
+            b.addEventListener("click", function () { select(i); });
+            strip.appendChild(b);
+            return b;
+        });
+
+        function select(index) {
+            tabs.forEach(function (tab, i) {
+                tab.hidden = i !== index;
+                buttons[i].setAttribute("aria-selected", i === index ? "true" : "false");
+            });
+        }
+
+        // open the tab a link points at, e.g. from {{#fig}}.
+        function selectTarget() {
+            var id = decodeURIComponent(location.hash.slice(1));
+            var target = id && document.getElementById(id);
+            tabs.forEach(function (tab, i) {
+                if (target && tab.contains(target)) {
+                    select(i);
+                }
+            });
+        }
+
+        container.insertBefore(strip, tabs[0]);
+        container.classList.add("drawio-tabs-ready");
+        select(0);
+        selectTarget();
+        window.addEventListener("hashchange", selectTarget);
+    }
+
+    document.querySelectorAll(".drawio-tabs").forEach(setup);
+})();
@@ -0,0 +1,81 @@
+// zoom/pan viewer for inlined draw.io diagrams, installed by mdbook-drawio.
+// without this script the diagram is shown as a plain static svg.
+(function () {
+    "use strict";
+
+    function setup(container) {
+        var content = container.querySelector(".drawio-zoom-content");
+        if (!content) {
+            return;
+        }
+        var scale = 1, x = 0, y = 0;
+
+        function apply() {
+            content.style.transform = "translate(" + x + "px, " + y + "px) scale(" + scale + ")";
+        }
+
+        function zoom(factor) {
+            scale = Math.min(Math.max(scale * factor, 0.2), 10);
+            apply();
+        }
+
+        function button(label, title, onclick) {
+            var b = document.createElement("button");
+            b.type = "button";
+            b.textContent = label;
+            b.title = title;
+            b.addEventListener("click", onclick);
+            return b;
+        }
+
+        var toolbar = document.createElement("div");
+        toolbar.className = "drawio-zoom-toolbar";
+        toolbar.appendChild(button("+", "Zoom in", function () { zoom(1.25); }));
+        toolbar.appendChild(button("−", "Zoom out", function () { zoom(0.8); }));
+        toolbar.appendChild(button("1:1", "Reset", function () {
+            scale = 1; x = 0; y = 0;
+            apply();
+        }));
+        if (container.requestFullscreen) {
+            toolbar.appendChild(button("⛶", "Full screen", function () {
+                if (document.fullscreenElement === container) {
+                    document.exitFullscreen();
+                } else {
+                    container.requestFullscreen();
+                }
+            }));
+        }
+        container.appendChild(toolbar);
+
+        // ctrl + wheel zooms, a plain wheel keeps scrolling the page.
+        container.addEventListener("wheel", function (e) {
+            if (e.ctrlKey) {
+                e.preventDefault();
+                zoom(e.deltaY < 0 ? 1.1 : 0.9);
+            }
+        }, { passive: false });
+
+        var start = null;
+        container.addEventListener("pointerdown", function (e) {
+            if (e.target.closest("a, button")) {
+                return;
+            }
+            start = { x: e.clientX - x, y: e.clientY - y };
+            container.classList.add("dragging");
+            container.setPointerCapture(e.pointerId);
+        });
+        container.addEventListener("pointermove", function (e) {
+            if (start) {
+                x = e.clientX - start.x;
+                y = e.clientY - start.y;
+                apply();
+            }
+        });
+        container.addEventListener("pointerup", function () {
+            start = null;
+            container.classList.remove("dragging");
+        });
+    }
+
+    document.querySelectorAll(".drawio-zoom").forEach(setup);
+})();
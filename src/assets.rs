@@ -0,0 +1,68 @@
+use mdbook::config::Config as BookConfig;
+use mdbook::errors::Result;
+use std::path::{Path, PathBuf};
+
+// css and js used by the html the preprocessor emits. `mdbook-drawio install`
+// writes them into the book's theme directory, they have to be listed in
+// output.html's additional-css/additional-js to be picked up.
+pub const ASSETS: [(&str, &str); 4] = [
+    ("drawio-zoom.css", include_str!("../assets/drawio-zoom.css")),
+    ("drawio-zoom.js", include_str!("../assets/drawio-zoom.js")),
+    ("drawio-tabs.css", include_str!("../assets/drawio-tabs.css")),
+    ("drawio-tabs.js", include_str!("../assets/drawio-tabs.js")),
+];
+
+/// writes the assets into `<book_dir>/theme`, returns the paths relative
+/// to the book that still need adding to book.toml.
+pub fn install<P: AsRef<Path>>(book_dir: P) -> Result<Vec<PathBuf>> {
+    let book_dir = book_dir.as_ref();
+    let theme_dir = book_dir.join("theme");
+    std::fs::create_dir_all(&theme_dir)?;
+
+    let html = BookConfig::from_disk(book_dir.join("book.toml"))?
+        .html_config()
+        .unwrap_or_default();
+
+    let mut missing = vec![];
+    for (name, content) in ASSETS.iter() {
+        log::info!("Installing {}", theme_dir.join(name).display());
+        std::fs::write(theme_dir.join(name), content)?;
+
+        let relative = PathBuf::from("theme").join(name);
+        let listed = if name.ends_with(".css") {
+            &html.additional_css
+        } else {
+            &html.additional_js
+        };
+        if !listed.contains(&relative) {
+            missing.push(relative);
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join("book.toml"),
+            "[book]\ntitle = \"test\"\n[output.html]\nadditional-css = [\"theme/drawio-zoom.css\"]\n",
+        )
+        .unwrap();
+
+        let missing = install(temp_dir.path()).unwrap();
+        assert_eq!(
+            missing,
+            vec![
+                PathBuf::from("theme/drawio-zoom.js"),
+                PathBuf::from("theme/drawio-tabs.css"),
+                PathBuf::from("theme/drawio-tabs.js"),
+            ]
+        );
+        assert!(temp_dir.path().join("theme/drawio-zoom.css").is_file());
+    }
+}
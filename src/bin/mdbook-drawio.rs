@@ -21,6 +21,13 @@ fn make_app() -> clap::Command<'static> {
                      .help("Root directory for the book,\nshould contain the configuration file (`book.toml`)"))
                 .about("Install the required asset files and include it in the config"),
         )
+        .subcommand(
+            Command::new("watch")
+                .arg(Arg::new("dir")
+                     .default_value(".")
+                     .help("Root directory for the book,\nshould contain the configuration file (`book.toml`)"))
+                .about("Export changed diagrams and reload the chapters showing them, next to `mdbook serve`"),
+        )
 }
 
 fn handle_supports(sub_args: &ArgMatches) -> ! {
@@ -34,6 +41,29 @@ fn handle_supports(sub_args: &ArgMatches) -> ! {
     }
 }
 
+fn handle_install(sub_args: &ArgMatches) -> Result<(), Error> {
+    let dir = sub_args.value_of("dir").expect("Required argument");
+    let missing = mdbook_drawio::install(dir)?;
+
+    if !missing.is_empty() {
+        let list = |ext: &str| {
+            missing
+                .iter()
+                .filter(|p| p.extension().is_some_and(|e| e == ext))
+                .map(|p| format!("\"{}\"", p.display()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        eprintln!(
+            "Add the installed assets to book.toml:\n\n[output.html]\n\
+             additional-css = [{}]\nadditional-js = [{}]",
+            list("css"),
+            list("js")
+        );
+    }
+    Ok(())
+}
+
 fn handle_preprocessing() -> Result<(), Error> {
     let (ctx, book) = CmdPreprocessor::parse_input(io::stdin())?;
 
@@ -62,6 +92,17 @@ fn main() {
 
     if let Some(sub_args) = matches.subcommand_matches("supports") {
         handle_supports(sub_args);
+    } else if let Some(sub_args) = matches.subcommand_matches("install") {
+        if let Err(e) = handle_install(sub_args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(sub_args) = matches.subcommand_matches("watch") {
+        let dir = sub_args.value_of("dir").expect("Required argument");
+        if let Err(e) = mdbook_drawio::watch(dir) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     } else if let Err(e) = handle_preprocessing() {
         eprintln!("{}", e);
         std::process::exit(1);
@@ -0,0 +1,153 @@
+use regex::Regex;
+use std::ops::Range;
+
+use crate::link::DiagramOptions;
+
+// diagrams written out in the chapter instead of linked, as fenced code
+// blocks. options go after the language, same as in a link:
+//   ```drawio page=Net&layers=base
+//   <mxGraphModel>...</mxGraphModel>
+//   ```
+// ```drawio-csv and ```drawio-mermaid blocks hold a source the diagram is
+// generated from.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    /// mxfile or mxGraphModel xml, or a compressed page.
+    Drawio,
+    /// draw.io's csv import format.
+    Csv,
+    /// a mermaid flowchart.
+    Mermaid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// where the block is in the chapter, fences included.
+    pub range: Range<usize>,
+    pub kind: BlockKind,
+    pub options: DiagramOptions,
+    /// everything between the fences.
+    pub source: String,
+}
+
+pub fn find_blocks(content: &str) -> Vec<CodeBlock> {
+    let regex_v =
+        Regex::new(r"(?m)^```drawio(-csv|-mermaid)?(?:[ \t]+([^\r\n]*))?\r?\n((?s:.*?))^```[ \t]*$").unwrap();
+
+    regex_v
+        .captures_iter(content)
+        .map(|caps| {
+            // options may also be separated by spaces.
+            let info = caps
+                .get(2)
+                .map(|i| i.as_str().split_whitespace().collect::<Vec<_>>().join("&"))
+                .unwrap_or_default();
+            let kind = match caps.get(1).map(|k| k.as_str()) {
+                Some("-csv") => BlockKind::Csv,
+                Some("-mermaid") => BlockKind::Mermaid,
+                _ => BlockKind::Drawio,
+            };
+            CodeBlock {
+                range: caps.get(0).unwrap().range(),
+                kind,
+                options: DiagramOptions::parse(&info),
+                source: caps[3].to_string(),
+            }
+        })
+        .collect()
+}
+
+/// the code in a chapter, fenced blocks and inline spans. what is in them is
+/// shown as written.
+pub fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    let fence_regex = Regex::new(r"(?m)^[ \t]{0,3}(`{3,}|~{3,})").unwrap();
+    let line_end = |at: usize| content[at..].find('\n').map_or(content.len(), |i| at + i);
+
+    // a block runs to a fence of the same kind at least as long, or to the
+    // end of the chapter.
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut at = 0;
+    while let Some(open) = fence_regex.find_at(content, at) {
+        let fence = open.as_str().trim_start();
+        let mut end = content.len();
+        let mut search = open.end();
+        while let Some(close) = fence_regex.find_at(content, search) {
+            let closing = close.as_str().trim_start();
+            search = line_end(close.end());
+            if closing.starts_with(&fence[..1])
+                && closing.len() >= fence.len()
+                && content[close.end()..search].trim().is_empty()
+            {
+                end = search;
+                break;
+            }
+        }
+        ranges.push(open.start()..end);
+        at = end;
+    }
+
+    // a span ends at the next run of as many backticks.
+    let runs: Vec<Range<usize>> = Regex::new(r"`+")
+        .unwrap()
+        .find_iter(content)
+        .map(|m| m.range())
+        .filter(|m| !ranges.iter().any(|r| r.contains(&m.start)))
+        .collect();
+    let mut i = 0;
+    while i < runs.len() {
+        let open = &runs[i];
+        match runs[i + 1..].iter().position(|r| r.len() == open.len()) {
+            Some(j) => {
+                ranges.push(open.start..runs[i + 1 + j].end);
+                i += j + 2;
+            }
+            None => i += 1,
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_blocks_test() {
+        let content = r#"
+```drawio
+<mxGraphModel><root/></mxGraphModel>
+```
+
+```drawio page=Net layers=base
+<mxfile/>
+```
+
+```rust
+fn main() {}
+```
+
+```drawio-mermaid
+graph TD
+```
+"#;
+        let blocks = find_blocks(content);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].kind, BlockKind::Drawio);
+        assert_eq!(blocks[2].kind, BlockKind::Mermaid);
+
+        assert_eq!(blocks[0].source, "<mxGraphModel><root/></mxGraphModel>\n");
+        assert_eq!(blocks[0].options, DiagramOptions::default());
+        assert!(content[blocks[0].range.clone()].ends_with("</mxGraphModel>\n```"));
+
+        assert_eq!(blocks[1].options.page.as_deref(), Some("Net"));
+        assert_eq!(blocks[1].options.layers, Some(vec!["base".to_string()]));
+    }
+
+    #[test]
+    fn code_ranges_test() {
+        let content = "a `b` ``c`d`` e\n````md\n```\nf\n````\ng ` h\n~~~\ni";
+        let code: Vec<&str> = code_ranges(content).into_iter().map(|r| &content[r]).collect();
+        assert_eq!(code, vec!["````md\n```\nf\n````", "~~~\ni", "`b`", "``c`d``"]);
+    }
+}
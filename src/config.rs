@@ -0,0 +1,308 @@
+use mdbook::config::Config as BookConfig;
+use mdbook::errors::Result;
+use mdbook::preprocess::PreprocessorContext;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// settings read from the [preprocessor.drawio] table of book.toml.
+// every field has a default so an empty (or missing) table is valid.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// when set a json summary of the build is written to this file,
+    /// relative to the book root.
+    pub stats_file: Option<PathBuf>,
+    /// how diagram colours follow the mdbook theme.
+    pub theme: Theme,
+    /// scale diagrams down to the content width using the svg viewBox.
+    pub responsive: bool,
+    /// strip comments and whitespace from the inlined svg.
+    pub minify: bool,
+    /// how diagrams are presented on the page.
+    pub viewer: Viewer,
+    /// what happens to the diagram draw.io embeds in exported svgs.
+    pub source: Source,
+    /// the link to edit a diagram added below it.
+    pub edit_link: EditLink,
+    /// stroke of cells named in a link's `highlight` option.
+    pub highlight_color: String,
+    pub highlight_width: f64,
+    /// opacity (0-100) of the other cells when a link asks to `dim` them.
+    pub dim_opacity: u32,
+    /// defaults for the size and placement options of links.
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub max_width: Option<String>,
+    pub scale: Option<f64>,
+    pub align: Option<Align>,
+    /// show the pages of a diagram linked as a whole as tabs.
+    pub tabs: bool,
+    /// file extensions of linked diagrams, besides .drawio.svg/.drawio.png.
+    pub extensions: Vec<String>,
+    /// title of a chapter listing every diagram page in the book, added at
+    /// the end when set.
+    pub list_of_figures: Option<String>,
+    /// how figures are numbered in their captions.
+    pub figure_numbering: Numbering,
+    /// what figures are called in captions and references.
+    pub figure_label: String,
+    /// directories `mdbook-drawio watch` watches for diagram changes, relative
+    /// to the book root. the src dir is always watched.
+    pub watch_dirs: Vec<PathBuf>,
+    /// fonts diagrams use, added as @font-face to chapters showing them.
+    pub fonts: Vec<Font>,
+    /// replace the text in svgs by paths with `text-to-path-command`.
+    pub text_to_path: bool,
+    /// run with the svg written to `{input}`, writing the result to
+    /// `{output}`.
+    pub text_to_path_command: String,
+    /// values for placeholders in diagram labels.
+    pub variables: BTreeMap<String, serde_json::Value>,
+    // the whole book.toml, placeholders not in `variables` are looked up in it.
+    #[serde(skip)]
+    pub book: BookConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stats_file: None,
+            theme: Theme::None,
+            responsive: true,
+            minify: false,
+            viewer: Viewer::Static,
+            source: Source::Keep,
+            edit_link: EditLink::None,
+            highlight_color: "#ff0000".to_string(),
+            highlight_width: 3.0,
+            dim_opacity: 25,
+            width: None,
+            height: None,
+            max_width: None,
+            scale: None,
+            align: None,
+            tabs: false,
+            extensions: vec!["drawio".to_string(), "dio".to_string()],
+            list_of_figures: None,
+            figure_numbering: Numbering::None,
+            figure_label: "Figure".to_string(),
+            watch_dirs: vec![],
+            fonts: vec![],
+            text_to_path: false,
+            text_to_path_command:
+                "inkscape --export-text-to-path --export-plain-svg --export-filename={output} {input}".to_string(),
+            variables: BTreeMap::new(),
+            book: BookConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// leave the exported colours alone.
+    #[default]
+    None,
+    /// map black and white to the theme's `var(--fg)` and `var(--bg)`.
+    CssVariables,
+    /// emit a light and a dark copy, shown depending on the theme class.
+    LightDark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    /// css placing a block of less than the content width.
+    pub fn style(&self) -> &'static str {
+        match self {
+            Align::Left => "display: block; margin-left: 0; margin-right: auto",
+            Align::Center => "display: block; margin-left: auto; margin-right: auto",
+            Align::Right => "display: block; margin-left: auto; margin-right: 0",
+        }
+    }
+}
+
+impl Config {
+    pub fn from_context(ctx: &PreprocessorContext) -> Result<Config> {
+        Config::from_book(&ctx.config)
+    }
+
+    pub fn from_book(book: &BookConfig) -> Result<Config> {
+        let mut config: Config = book
+            .get_deserialized_opt("preprocessor.drawio")?
+            .unwrap_or_default();
+        config.book = book.clone();
+        Ok(config)
+    }
+
+    /// where `path` (relative to the book root) is in the book's repository,
+    /// from output.html's `edit-url-template` or else `git-repository-url`.
+    /// `raw` gives the file itself instead of a page editing it.
+    pub fn repository_url(&self, path: &Path, raw: bool) -> Option<String> {
+        let html = self.book.html_config()?;
+        let path = path.to_string_lossy().replace('\\', "/");
+        match (html.edit_url_template, html.git_repository_url) {
+            // github and gitlab both serve files at /raw/ where /edit/ is.
+            (Some(template), _) if raw => Some(template.replace("{path}", &path).replacen("/edit/", "/raw/", 1)),
+            (Some(template), _) => Some(template.replace("{path}", &path)),
+            (None, Some(repository)) => Some(format!(
+                "{}/{}/HEAD/{}",
+                repository.trim_end_matches('/'),
+                if raw { "raw" } else { "blob" },
+                path
+            )),
+            (None, None) => None,
+        }
+    }
+
+    /// the value of a placeholder, from the `variables` table or else any
+    /// book.toml entry by its dotted name, e.g. `book.title`.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.variables.get(name) {
+            return Some(match value {
+                serde_json::Value::String(s) => s.clone(),
+                v => v.to_string(),
+            });
+        }
+
+        let book = &self.book.book;
+        match name {
+            "book.title" => book.title.clone(),
+            "book.description" => book.description.clone(),
+            "book.authors" => Some(book.authors.join(", ")),
+            "book.language" => book.language.clone(),
+            _ => self.book.get(name).map(|v| {
+                v.as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| v.to_string())
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Numbering {
+    /// captions are only the link's text.
+    #[default]
+    None,
+    /// "Figure 3.2", the second figure of chapter 3.
+    Chapter,
+    /// "Figure 7", counted through the whole book.
+    Book,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Viewer {
+    /// the inlined svg.
+    #[default]
+    Static,
+    /// zoom/pan controls and a full-screen toggle from drawio-zoom.js.
+    Zoom,
+    /// draw.io's own viewer with the page xml, pages and layers can be browsed.
+    #[serde(rename = "drawio-viewer")]
+    Drawio,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Font {
+    /// the name diagrams use for it.
+    pub family: String,
+    /// a font file relative to the book root, embedded, or a url.
+    pub src: String,
+    pub weight: Option<String>,
+    pub style: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// leave the `content` attribute, the svg can be opened in draw.io.
+    #[default]
+    Keep,
+    /// drop it, it is about as big as the rest of the svg.
+    Strip,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EditLink {
+    #[default]
+    None,
+    /// opens the diagram in the draw.io web app.
+    Drawio,
+    /// the file in the book's repository, like mdbook's edit button.
+    Source,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn variable_test() {
+        let book = BookConfig::from_str(
+            r#"
+[book]
+title = "Manual"
+
+[preprocessor.drawio.variables]
+version = "1.2"
+build = 7
+
+[product]
+name = "Widget"
+"#,
+        )
+        .unwrap();
+        let mut config: Config = book
+            .get_deserialized_opt("preprocessor.drawio")
+            .unwrap()
+            .unwrap();
+        config.book = book;
+
+        assert_eq!(config.variable("version").as_deref(), Some("1.2"));
+        assert_eq!(config.variable("build").as_deref(), Some("7"));
+        assert_eq!(config.variable("book.title").as_deref(), Some("Manual"));
+        assert_eq!(config.variable("product.name").as_deref(), Some("Widget"));
+        assert_eq!(config.variable("missing"), None);
+    }
+
+    #[test]
+    fn repository_url_test() {
+        let mut config = Config::default();
+        let path = Path::new("src/arch/overview.drawio");
+        assert_eq!(config.repository_url(path, false), None);
+
+        config.book = BookConfig::from_str(
+            "[output.html]\ngit-repository-url = \"https://github.com/acme/manual/\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.repository_url(path, true).as_deref(),
+            Some("https://github.com/acme/manual/raw/HEAD/src/arch/overview.drawio")
+        );
+
+        config.book = BookConfig::from_str(
+            "[output.html]\nedit-url-template = \"https://github.com/acme/docs/edit/main/manual/{path}\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.repository_url(path, false).as_deref(),
+            Some("https://github.com/acme/docs/edit/main/manual/src/arch/overview.drawio")
+        );
+        assert_eq!(
+            config.repository_url(path, true).as_deref(),
+            Some("https://github.com/acme/docs/raw/main/manual/src/arch/overview.drawio")
+        );
+    }
+}
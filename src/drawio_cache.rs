@@ -74,6 +74,42 @@ impl DrawIoCache {
         }
     }
 
+    /// an entry named by the content it was exported from, unlike
+    /// `get_diagram` it never goes stale.
+    pub fn get_page<P: AsRef<Path>>(&self, path: P, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.get_diagram_cache_path(path, key)).ok()
+    }
+
+    /// adds an entry named by content, `<hash>/<file>` or `<hash>/<edits>/<file>`.
+    /// `file` under any other hash was exported from content the diagram no
+    /// longer has, those entries are removed.
+    pub fn add_page<P: AsRef<Path>>(&self, path: P, key: &str, content: &str) -> Result<(), String> {
+        self.add_diagram(&path, key, content)?;
+        let (hash, file) = match (key.split('/').next(), key.rsplit('/').next()) {
+            (Some(hash), Some(file)) if hash != file => (hash, file),
+            _ => return Ok(()),
+        };
+        let dir = self.get_diagram_cache_path(&path, "");
+        let hashes = std::fs::read_dir(&dir).map_err(|e| e.to_string())?;
+        for entry in hashes.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // other entries of the diagram, mtime based or not a page at all.
+            if name == hash || name.len() != hash.len() || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            let stale = entry.path();
+            let edits = std::fs::read_dir(&stale).into_iter().flatten().filter_map(|e| e.ok());
+            for edit in edits.map(|e| e.path()).filter(|p| p.is_dir()) {
+                std::fs::remove_file(edit.join(file)).ok();
+                // only removed once empty.
+                std::fs::remove_dir(edit).ok();
+            }
+            std::fs::remove_file(stale.join(file)).ok();
+            std::fs::remove_dir(stale).ok();
+        }
+        Ok(())
+    }
+
     /// removes entry from cache. 
     pub fn clear_diagram<P: AsRef<Path>>(&self, path: P, page: &str) {
         let d_path = self.get_diagram_cache_path(path, page);
@@ -0,0 +1,215 @@
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::html::escape;
+use crate::svg;
+
+// every diagram inlined while the book is processed, for `{{#fig}}`
+// references and the list of figures added at the end.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Figure {
+    /// the diagram file and page shown.
+    pub diagram: PathBuf,
+    pub page: String,
+    pub caption: String,
+    /// "3.2", unless figures aren't numbered.
+    pub number: Option<String>,
+    /// the chapter showing it, relative to the src dir.
+    pub chapter: PathBuf,
+    pub chapter_name: String,
+    /// id of the element the diagram is wrapped in.
+    pub anchor: String,
+    /// the svg (or <img>) shown in the list, links relative to the src dir.
+    pub thumbnail: String,
+}
+
+impl Figure {
+    /// "Figure 3.2: caption", as shown below the diagram.
+    pub fn full_caption(&self, config: &Config) -> String {
+        match (&self.number, self.caption.as_str()) {
+            (Some(n), "") => format!("{} {}", config.figure_label, n),
+            (Some(n), c) => format!("{} {}: {}", config.figure_label, n, c),
+            (None, c) => c.to_string(),
+        }
+    }
+
+    /// `full_caption` as html. the caption has a span of its own so tabs can
+    /// hide the page name their label shows already.
+    pub fn caption_html(&self, config: &Config) -> String {
+        match (&self.number, self.caption.as_str()) {
+            (_, "") => escape(&self.full_caption(config)),
+            (Some(n), c) => format!(
+                "{} {}<span class=\"drawio-caption\">: {}</span>",
+                escape(&config.figure_label),
+                escape(n),
+                escape(c)
+            ),
+            (None, c) => format!("<span class=\"drawio-caption\">{}</span>", escape(c)),
+        }
+    }
+
+    // what `{{#fig id}}` refers to, slugged like the anchor.
+    fn id(&self) -> &str {
+        self.anchor.strip_prefix("drawio-").unwrap_or(&self.anchor)
+    }
+}
+
+/// replaces `{{#fig id}}` in a chapter with a link to the figure, "Figure 3.2"
+/// or its caption when figures aren't numbered. code is left alone.
+pub fn replace_references(content: &str, figures: &[Figure], chapter: &Path, config: &Config) -> String {
+    let regex_v = Regex::new(r"\{\{#fig\s+([^}\s]+)\s*\}\}").unwrap();
+    let code = crate::block::code_ranges(content);
+    regex_v
+        .replace_all(content, |caps: &Captures| {
+            let start = caps.get(0).unwrap().start();
+            if code.iter().any(|r| r.contains(&start)) {
+                return caps[0].to_string();
+            }
+            let id = crate::utilities::slug(&caps[1]);
+            let figure = match figures.iter().find(|f| f.id() == id) {
+                Some(f) => f,
+                None => {
+                    log::warn!("No figure {} referenced in {}", &caps[1], chapter.display());
+                    return caps[0].to_string();
+                }
+            };
+            let text = match (&figure.number, figure.caption.as_str()) {
+                (Some(n), _) => format!("{} {}", config.figure_label, n),
+                (None, "") => figure.id().to_string(),
+                (None, c) => c.to_string(),
+            };
+            let href = if figure.chapter == chapter {
+                String::new()
+            } else {
+                let dir = chapter.parent().unwrap_or(Path::new(""));
+                crate::utilities::relative_path(figure.chapter.with_extension("html"), dir)
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            };
+            format!("<a href=\"{}#{}\">{}</a>", escape(&href), figure.anchor, escape(&text))
+        })
+        .to_string()
+}
+
+/// the content of the list of figures chapter, saved at `path` (relative
+/// to the src dir). each page shown in the book is listed once along with
+/// the chapters it is in.
+pub fn list_of_figures(figures: &[Figure], title: &str, path: &Path, config: &Config) -> String {
+    let mut pages: Vec<Vec<&Figure>> = vec![];
+    for figure in figures {
+        match pages
+            .iter_mut()
+            .find(|p| p[0].diagram == figure.diagram && p[0].page == figure.page)
+        {
+            Some(uses) => uses.push(figure),
+            None => pages.push(vec![figure]),
+        }
+    }
+
+    let root = path.parent().unwrap_or(Path::new(""));
+    let mut content = format!("# {}\n\n<div class=\"drawio-figures\">\n", title);
+    for (i, uses) in pages.iter().enumerate() {
+        let first = uses.iter().find(|f| !f.caption.is_empty()).unwrap_or(&uses[0]);
+        let caption = match first.full_caption(config) {
+            c if c.is_empty() => first.page.clone(),
+            c => c,
+        };
+        let links: Vec<String> = uses
+            .iter()
+            .map(|f| {
+                let href = crate::utilities::relative_path(f.chapter.with_extension("html"), root)
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                format!("<a href=\"{}#{}\">{}</a>", escape(&href), f.anchor, escape(&f.chapter_name))
+            })
+            .collect();
+        // the same svg may be in the page twice, its ids must not clash.
+        let thumbnail = match uses[0].thumbnail.starts_with("<svg") {
+            true => svg::process(&uses[0].thumbnail, config, config.theme, &format!("figure{}-", i + 1)),
+            false => uses[0].thumbnail.clone(),
+        };
+        content += &format!(
+            "<div class=\"drawio-figure-entry\">\
+             <div class=\"drawio-thumbnail\" style=\"max-width: 200px;\">{}</div>\
+             <p><strong>{}</strong><br>{}</p></div>\n",
+            thumbnail,
+            escape(&caption),
+            links.join(", ")
+        );
+    }
+    content += "</div>\n";
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_of_figures_test() {
+        let figure = |chapter: &str, caption: &str, page: &str| Figure {
+            diagram: PathBuf::from("src/arch.drawio"),
+            page: page.to_string(),
+            caption: caption.to_string(),
+            number: None,
+            chapter: PathBuf::from(chapter),
+            chapter_name: chapter.to_string(),
+            anchor: format!("drawio-arch-{}", page),
+            thumbnail: "<svg viewBox=\"0 0 10 10\"><g id=\"a\"/></svg>".to_string(),
+        };
+        let figures = [
+            figure("intro.md", "", "net"),
+            figure("guide/setup.md", "Network", "net"),
+            figure("guide/setup.md", "", "db"),
+        ];
+
+        let content = list_of_figures(&figures, "Figures", Path::new("figures.md"), &Config::default());
+        assert!(content.starts_with("# Figures\n"));
+        assert_eq!(content.matches("drawio-figure-entry").count(), 2);
+        // the first caption found, else the page.
+        assert!(content.contains("<strong>Network</strong><br><a href=\"intro.html#drawio-arch-net\">"));
+        assert!(content.contains("<a href=\"guide/setup.html#drawio-arch-net\">guide/setup.md</a>"));
+        assert!(content.contains("<strong>db</strong>"));
+        assert!(content.contains("id=\"figure2-a\""));
+    }
+
+    #[test]
+    fn replace_references_test() {
+        let config = Config::default();
+        let mut figure = Figure {
+            diagram: PathBuf::from("src/arch.drawio"),
+            page: "Overview".to_string(),
+            caption: "Overview".to_string(),
+            number: Some("3.2".to_string()),
+            chapter: PathBuf::from("design/arch.md"),
+            chapter_name: "Architecture".to_string(),
+            anchor: "drawio-arch-overview".to_string(),
+            thumbnail: String::new(),
+        };
+        assert_eq!(figure.full_caption(&config), "Figure 3.2: Overview");
+        assert_eq!(
+            figure.caption_html(&config),
+            "Figure 3.2<span class=\"drawio-caption\">: Overview</span>"
+        );
+
+        let content = "See {{#fig arch-overview}} and {{#fig missing}}.";
+        assert_eq!(
+            replace_references(content, &[figure.clone()], Path::new("intro/start.md"), &config),
+            "See <a href=\"../design/arch.html#drawio-arch-overview\">Figure 3.2</a> and {{#fig missing}}."
+        );
+        figure.number = None;
+        assert_eq!(
+            replace_references("{{#fig arch-overview}}", &[figure.clone()], Path::new("design/arch.md"), &config),
+            "<a href=\"#drawio-arch-overview\">Overview</a>"
+        );
+
+        // ids are matched the way anchors are made, code is shown as written.
+        let content = "{{#fig Arch_Overview}} `{{#fig arch-overview}}`\n```\n{{#fig arch-overview}}\n```";
+        assert_eq!(
+            replace_references(content, &[figure], Path::new("design/arch.md"), &config),
+            "<a href=\"#drawio-arch-overview\">Overview</a> `{{#fig arch-overview}}`\n```\n{{#fig arch-overview}}\n```"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use regex::Regex;
+use std::path::Path;
+use std::process;
+
+use crate::config::Font;
+
+// custom fonts in diagrams. the exporter draws labels with whatever fonts
+// are installed, browsers only have the system ones either, so the fonts
+// from book.toml are added as @font-face next to the diagrams using them.
+// for text that looks the same everywhere it can be turned into paths by
+// an external tool (inkscape by default).
+
+/// the fonts from `fonts` the svg uses, by the families in its
+/// `font-family` attributes and css.
+pub fn used<'a>(svg: &str, fonts: &'a [Font]) -> Vec<&'a Font> {
+    // html labels are in attributes, their quotes escaped.
+    let svg = svg.replace("&quot;", "\"").replace("&#39;", "'");
+    let family_regex =
+        Regex::new(r#"font-family(?:\s*=\s*"([^"]*)"|\s*=\s*'([^']*)'|\s*:\s*((?:"[^"]*"|'[^']*'|[^;"'}<>])*))"#)
+            .unwrap();
+    let families: Vec<String> = family_regex
+        .captures_iter(&svg)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)).or_else(|| caps.get(3)))
+        .flat_map(|value| {
+            value
+                .as_str()
+                .split(',')
+                .map(|f| f.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    fonts
+        .iter()
+        .filter(|f| families.contains(&f.family.to_lowercase()))
+        .collect()
+}
+
+/// a `<style>` with an @font-face for every font, files are embedded as
+/// data urls.
+pub fn font_faces(fonts: &[&Font]) -> String {
+    let mut css = String::new();
+    for font in fonts {
+        let src = match font_src(&font.src) {
+            Ok(src) => src,
+            Err(e) => {
+                log::error!("Failed to read font {}: {}", font.src, e);
+                continue;
+            }
+        };
+        css += &format!("@font-face {{ font-family: {}; src: {};", css_string(&font.family), src);
+        if let Some(weight) = &font.weight {
+            css += &format!(" font-weight: {};", weight);
+        }
+        if let Some(style) = &font.style {
+            css += &format!(" font-style: {};", style);
+        }
+        css += " }\n";
+    }
+    match css.is_empty() {
+        true => css,
+        false => format!("<style>\n{}</style>\n", css),
+    }
+}
+
+// a quoted css string, safe inside a <style>.
+fn css_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('<', "\\3c ");
+    format!("\"{}\"", escaped)
+}
+
+// `url(...) format(...)` for a font file or url.
+fn font_src(src: &str) -> std::io::Result<String> {
+    let extension = Path::new(src)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let (mime, format) = match extension.as_str() {
+        "woff2" => ("font/woff2", "woff2"),
+        "woff" => ("font/woff", "woff"),
+        "otf" => ("font/otf", "opentype"),
+        _ => ("font/ttf", "truetype"),
+    };
+    let url = if src.contains("://") {
+        src.to_string()
+    } else {
+        format!("data:{};base64,{}", mime, base64::encode(std::fs::read(src)?))
+    };
+    Ok(format!("url(\"{}\") format(\"{}\")", url, format))
+}
+
+/// leaves only the plain svg text of labels. draw.io writes them as html
+/// in a <foreignObject> with a <text> fallback, tools converting text to
+/// paths only understand the latter.
+pub fn text_only(svg: &str) -> String {
+    let regex_v = Regex::new(r"(?s)<foreignObject\b.*?</foreignObject>").unwrap();
+    regex_v.replace_all(svg, "").to_string()
+}
+
+/// runs `command` over the svg to turn its text into paths.
+pub fn text_to_path(svg: &str, command: &str) -> Result<String, String> {
+    let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let input = temp_dir.path().join("input.svg");
+    let output = temp_dir.path().join("output.svg");
+    std::fs::write(&input, text_only(svg)).map_err(|e| e.to_string())?;
+
+    let args: Vec<String> = command
+        .split_whitespace()
+        .map(|a| {
+            a.replace("{input}", &input.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        })
+        .collect();
+    let (program, args) = args.split_first().ok_or("no text-to-path-command")?;
+    log::debug!("{} {}", program, args.join(" "));
+    let result = process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if !result.status.success() {
+        return Err(String::from_utf8_lossy(&result.stderr).into_owned());
+    }
+    std::fs::read_to_string(&output).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_faces_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("acme.woff2");
+        std::fs::write(&file, b"font").unwrap();
+        let fonts = [
+            Font {
+                family: "Acme Sans".to_string(),
+                src: file.to_string_lossy().into_owned(),
+                weight: Some("bold".to_string()),
+                style: None,
+            },
+            Font {
+                family: "Other".to_string(),
+                src: "https://fonts.example.com/other.ttf".to_string(),
+                weight: None,
+                style: None,
+            },
+        ];
+
+        let svg = r#"<svg><text font-family="Acme Sans">a</text></svg>"#;
+        let used = used(svg, &fonts);
+        assert_eq!(used.len(), 1);
+        // families named alike don't count, html labels do.
+        let acme = Font {
+            family: "Acme".to_string(),
+            ..fonts[0].clone()
+        };
+        assert!(super::used(svg, std::slice::from_ref(&acme)).is_empty());
+        let label = r#"<div style="font-family: &quot;Other&quot;, Helvetica; font-size: 12px">a</div>"#;
+        assert_eq!(super::used(label, &fonts), vec![&fonts[1]]);
+        assert_eq!(
+            font_faces(&used),
+            "<style>\n@font-face { font-family: \"Acme Sans\"; \
+             src: url(\"data:font/woff2;base64,Zm9udA==\") format(\"woff2\"); font-weight: bold; }\n</style>\n"
+        );
+        assert!(font_faces(&[&fonts[1]])
+            .contains("src: url(\"https://fonts.example.com/other.ttf\") format(\"truetype\");"));
+        assert_eq!(css_string("A \"B\" </style>"), "\"A \\\"B\\\" \\3c /style>\"");
+    }
+
+    #[test]
+    fn text_only_test() {
+        let svg = r#"<svg><switch><foreignObject><div>a</div></foreignObject><text>a</text></switch></svg>"#;
+        assert_eq!(text_only(svg), "<svg><switch><text>a</text></switch></svg>");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn text_to_path_test() {
+        let svg = r#"<svg><switch><foreignObject><div>a</div></foreignObject><text>a</text></switch></svg>"#;
+        // any command reading {input} and writing {output} will do.
+        assert_eq!(
+            text_to_path(svg, "cp {input} {output}").unwrap(),
+            "<svg><switch><text>a</text></switch></svg>"
+        );
+        assert!(text_to_path(svg, "false").is_err());
+    }
+}
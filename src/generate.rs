@@ -0,0 +1,611 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::mxfile::escape;
+
+// draw.io can build diagrams from csv (Arrange > Insert > Advanced > CSV)
+// and from mermaid, but only in the editor. the exporter can't, so the
+// sources are turned into an <mxGraphModel> here: a subset of the csv
+// configuration and mermaid flowcharts, laid out in levels.
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Node {
+    id: String,
+    label: String,
+    style: String,
+    width: f64,
+    height: f64,
+    // set when the position is given, otherwise filled in by `layout`.
+    position: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Edge {
+    source: String,
+    target: String,
+    label: String,
+    style: String,
+}
+
+#[derive(Debug)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    // levels go left to right instead of top to bottom.
+    horizontal: bool,
+    // right to left or bottom to top.
+    reversed: bool,
+    node_spacing: f64,
+    level_spacing: f64,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            edges: vec![],
+            horizontal: false,
+            reversed: false,
+            node_spacing: 40.0,
+            level_spacing: 100.0,
+        }
+    }
+}
+
+const DEFAULT_STYLE: &str = "rounded=0;whiteSpace=wrap;html=1;";
+const DEFAULT_EDGE_STYLE: &str = "edgeStyle=orthogonalEdgeStyle;rounded=0;html=1;";
+
+// a size that fits the label, roughly, as draw.io's "auto" does.
+fn auto_size(label: &str) -> (f64, f64) {
+    let text = Regex::new(r"<[^>]*>").unwrap().replace_all(
+        &Regex::new(r"(?i)<br\s*/?>").unwrap().replace_all(label, "\n"),
+        "",
+    ).to_string();
+    let lines = text.lines().count().max(1) as f64;
+    let longest = text.lines().map(|l| l.chars().count()).max().unwrap_or(0) as f64;
+    ((longest * 7.0 + 20.0).max(80.0), (lines * 16.0 + 24.0).max(40.0))
+}
+
+// %column% is replaced by that column of the row.
+fn fill(template: &str, row: &HashMap<&str, &str>) -> String {
+    Regex::new(r"%([^%\s;]+)%")
+        .unwrap()
+        .replace_all(template, |caps: &regex::Captures| {
+            row.get(&caps[1]).map(|v| v.to_string()).unwrap_or_default()
+        })
+        .to_string()
+}
+
+// rows of comma separated values, quoted values may hold commas, quotes
+// (doubled) and line breaks.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut value)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut value));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => value.push(c),
+        }
+    }
+    if !value.is_empty() || !row.is_empty() {
+        row.push(value);
+        rows.push(row);
+    }
+    rows.retain(|r: &Vec<String>| r.iter().any(|v| !v.trim().is_empty()));
+    rows
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Connect {
+    from: String,
+    to: String,
+    #[serde(default)]
+    invert: bool,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    style: Option<String>,
+}
+
+/// an <mxGraphModel> from draw.io's csv import format: `# key: value`
+/// configuration lines followed by the csv with a header row.
+pub fn csv(source: &str) -> Result<String, String> {
+    let mut config: HashMap<String, String> = HashMap::new();
+    let mut connects = vec![];
+    let mut data = String::new();
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("##") || (trimmed.is_empty() && data.is_empty()) {
+            continue;
+        }
+        match trimmed.strip_prefix('#') {
+            Some(setting) if data.is_empty() => {
+                let (key, value) = setting.split_once(':').unwrap_or((setting, ""));
+                let (key, value) = (key.trim().to_lowercase(), value.trim().to_string());
+                if key == "connect" {
+                    let connect: Connect = serde_json::from_str(&value)
+                        .map_err(|e| format!("invalid connect {}: {}", value, e))?;
+                    connects.push(connect);
+                } else {
+                    config.insert(key, value);
+                }
+            }
+            _ => {
+                data += line;
+                data.push('\n');
+            }
+        }
+    }
+
+    let rows = parse_csv(&data);
+    let (header, rows) = rows.split_first().ok_or("csv has no header row")?;
+    let header: Vec<&str> = header.iter().map(|h| h.trim()).collect();
+    let setting = |key: &str| config.get(key).map(String::as_str);
+    let number = |key: &str| setting(key).and_then(|v| v.parse::<f64>().ok());
+    let styles: HashMap<String, String> = match setting("styles") {
+        Some(s) => serde_json::from_str(s).map_err(|e| format!("invalid styles: {}", e))?,
+        None => HashMap::new(),
+    };
+
+    let mut graph = Graph::default();
+    let layout = setting("layout").unwrap_or("auto").to_lowercase();
+    graph.horizontal = layout.starts_with("horizontal");
+    if let Some(n) = number("nodespacing") {
+        graph.node_spacing = n;
+    }
+    if let Some(n) = number("levelspacing") {
+        graph.level_spacing = n;
+    }
+
+    let rows: Vec<HashMap<&str, &str>> = rows
+        .iter()
+        .map(|r| {
+            header
+                .iter()
+                .zip(r.iter())
+                .map(|(h, v)| (*h, v.trim()))
+                .collect()
+        })
+        .collect();
+    let label = setting("label").unwrap_or("%name%");
+    for (i, row) in rows.iter().enumerate() {
+        let id = match setting("identity").and_then(|c| row.get(c)) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => format!("row-{}", i + 1),
+        };
+        let style = setting("stylename")
+            .and_then(|c| row.get(c))
+            .and_then(|name| styles.get(*name))
+            .map(String::as_str)
+            .or_else(|| setting("style"))
+            .unwrap_or(DEFAULT_STYLE);
+        let label = fill(label, row);
+        let (auto_width, auto_height) = auto_size(&label);
+        let position = match (setting("left").and_then(|c| row.get(c)), setting("top").and_then(|c| row.get(c))) {
+            (Some(x), Some(y)) if layout == "none" => x.parse().ok().zip(y.parse().ok()),
+            _ => None,
+        };
+        graph.nodes.push(Node {
+            id,
+            label,
+            style: fill(style, row),
+            width: number("width").unwrap_or(auto_width),
+            height: number("height").unwrap_or(auto_height),
+            position,
+        });
+    }
+
+    // `from` holds (comma separated) values of the `to` column of other rows.
+    for connect in &connects {
+        for (i, row) in rows.iter().enumerate() {
+            let refs = row.get(connect.from.as_str()).copied().unwrap_or("");
+            for value in refs.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                let Some(j) = rows.iter().position(|r| r.get(connect.to.as_str()) == Some(&value)) else {
+                    log::warn!("No row with {} = {}", connect.to, value);
+                    continue;
+                };
+                let (mut source, mut target) = (graph.nodes[i].id.clone(), graph.nodes[j].id.clone());
+                if connect.invert {
+                    std::mem::swap(&mut source, &mut target);
+                }
+                graph.edges.push(Edge {
+                    source,
+                    target,
+                    label: connect.label.clone(),
+                    style: connect.style.clone().unwrap_or_else(|| DEFAULT_EDGE_STYLE.to_string()),
+                });
+            }
+        }
+    }
+
+    layout_levels(&mut graph);
+    Ok(model(&graph))
+}
+
+// mermaid node shapes, longest opening bracket first.
+const SHAPES: &[(&str, &str, &str)] = &[
+    ("([", "])", "rounded=1;arcSize=50;whiteSpace=wrap;html=1;"),
+    ("[(", ")]", "shape=cylinder3;whiteSpace=wrap;html=1;"),
+    ("((", "))", "ellipse;aspect=fixed;whiteSpace=wrap;html=1;"),
+    ("{{", "}}", "shape=hexagon;perimeter=hexagonPerimeter2;whiteSpace=wrap;html=1;"),
+    ("[", "]", DEFAULT_STYLE),
+    ("(", ")", "rounded=1;whiteSpace=wrap;html=1;"),
+    ("{", "}", "rhombus;whiteSpace=wrap;html=1;"),
+    (">", "]", "shape=offPageConnector;direction=north;whiteSpace=wrap;html=1;"),
+];
+
+// reads `id` or `id[label]` from the start of `s`, the rest is returned.
+fn mermaid_node<'a>(s: &'a str, graph: &mut Graph) -> Result<(String, &'a str), String> {
+    let s = s.trim_start();
+    let id_len = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    if id_len == 0 {
+        return Err(format!("expected a node at: {}", s));
+    }
+    let (id, mut rest) = s.split_at(id_len);
+
+    let mut shape = None;
+    if let Some((open, close, style)) = SHAPES.iter().find(|(open, ..)| rest.starts_with(open)) {
+        let inner = &rest[open.len()..];
+        // quoted labels may contain the closing bracket.
+        let (label, after) = match inner.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').ok_or("unterminated label")?;
+                let after = quoted[end + 1..].trim_start();
+                (&quoted[..end], after.strip_prefix(close).ok_or("unterminated node")?)
+            }
+            None => {
+                let end = inner.find(close).ok_or("unterminated node")?;
+                (&inner[..end], &inner[end + close.len()..])
+            }
+        };
+        shape = Some((label.trim().to_string(), style.to_string()));
+        rest = after;
+    }
+
+    match graph.nodes.iter_mut().find(|n| n.id == id) {
+        // the shape can be given on any mention of the node.
+        Some(node) => {
+            if let Some((label, style)) = shape {
+                node.label = label;
+                node.style = style;
+            }
+        }
+        None => {
+            let (label, style) = shape.unwrap_or_else(|| (id.to_string(), DEFAULT_STYLE.to_string()));
+            graph.nodes.push(Node {
+                id: id.to_string(),
+                label,
+                style,
+                ..Default::default()
+            });
+        }
+    }
+    Ok((id.to_string(), rest))
+}
+
+// `A & B` from the start of `s`.
+fn mermaid_nodes<'a>(s: &'a str, graph: &mut Graph) -> Result<(Vec<String>, &'a str), String> {
+    let (id, mut rest) = mermaid_node(s, graph)?;
+    let mut ids = vec![id];
+    while let Some(more) = rest.trim_start().strip_prefix('&') {
+        let (id, r) = mermaid_node(more, graph)?;
+        ids.push(id);
+        rest = r;
+    }
+    Ok((ids, rest))
+}
+
+// splits a line at the `;` between statements, not those in labels.
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let (mut depth, mut quoted, mut edge_label) = (0, false, false);
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            '|' if depth == 0 => edge_label = !edge_label,
+            ';' if depth <= 0 && !edge_label => {
+                statements.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&line[start..]);
+    statements
+}
+
+/// an <mxGraphModel> from a mermaid flowchart.
+pub fn mermaid(source: &str) -> Result<String, String> {
+    let link_regex = Regex::new(
+        r"^\s*(?:(?:--|==|-\.)\s*([^-=.|>][^|>]*?)\s*)?(-{2,}>|-{3,}|-\.+->|-\.+-|\.-+>|\.-+|={2,}>|={3,})(?:\|([^|]*)\|)?",
+    )
+    .unwrap();
+
+    let mut graph = Graph::default();
+    let mut statements = source
+        .lines()
+        .flat_map(split_statements)
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("%%"));
+
+    let header = statements.next().ok_or("empty mermaid diagram")?;
+    let mut words = header.split_whitespace();
+    match words.next() {
+        Some("graph") | Some("flowchart") => {}
+        _ => return Err(format!("only mermaid flowcharts are supported, found: {}", header)),
+    }
+    match words.next().unwrap_or("TB") {
+        "LR" => graph.horizontal = true,
+        "RL" => {
+            graph.horizontal = true;
+            graph.reversed = true;
+        }
+        "BT" => graph.reversed = true,
+        _ => {}
+    }
+
+    for statement in statements {
+        let keyword = statement.split_whitespace().next().unwrap_or("");
+        if matches!(
+            keyword,
+            "subgraph" | "end" | "direction" | "classDef" | "class" | "style" | "linkStyle" | "click"
+        ) {
+            log::debug!("Ignoring mermaid statement: {}", statement);
+            continue;
+        }
+
+        let (mut sources, mut rest) = mermaid_nodes(statement, &mut graph)?;
+        while let Some(caps) = link_regex.captures(rest) {
+            let arrow = &caps[2];
+            let label = caps.get(1).or_else(|| caps.get(3)).map(|l| l.as_str().trim()).unwrap_or("");
+            let mut style = DEFAULT_EDGE_STYLE.to_string();
+            if arrow.contains('.') {
+                style += "dashed=1;";
+            }
+            if arrow.starts_with('=') {
+                style += "strokeWidth=2;";
+            }
+            if !arrow.ends_with('>') {
+                style += "endArrow=none;";
+            }
+
+            let (targets, r) = mermaid_nodes(&rest[caps[0].len()..], &mut graph)?;
+            for source in &sources {
+                for target in &targets {
+                    graph.edges.push(Edge {
+                        source: source.clone(),
+                        target: target.clone(),
+                        label: label.to_string(),
+                        style: style.clone(),
+                    });
+                }
+            }
+            sources = targets;
+            rest = r;
+        }
+        if !rest.trim().is_empty() {
+            return Err(format!("could not read mermaid statement: {}", statement));
+        }
+    }
+
+    for node in &mut graph.nodes {
+        let (width, height) = auto_size(&node.label);
+        node.width = width;
+        node.height = height;
+    }
+    layout_levels(&mut graph);
+    Ok(model(&graph))
+}
+
+// places each node one level after the furthest node pointing at it, the
+// nodes of a level side by side in the order they were first seen.
+fn layout_levels(graph: &mut Graph) {
+    let index: HashMap<String, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.clone(), i))
+        .collect();
+    let edges: Vec<(usize, usize)> = graph
+        .edges
+        .iter()
+        .filter_map(|e| Some((*index.get(&e.source)?, *index.get(&e.target)?)))
+        .filter(|(s, t)| s != t)
+        .collect();
+
+    // edges closing a cycle are left out, found by walking the graph.
+    let mut state = vec![0u8; graph.nodes.len()];
+    let mut back = vec![false; edges.len()];
+    fn visit(n: usize, edges: &[(usize, usize)], state: &mut [u8], back: &mut [bool]) {
+        state[n] = 1;
+        for (i, &(s, t)) in edges.iter().enumerate() {
+            if s != n {
+                continue;
+            }
+            match state[t] {
+                0 => visit(t, edges, state, back),
+                1 => back[i] = true,
+                _ => {}
+            }
+        }
+        state[n] = 2;
+    }
+    for n in 0..graph.nodes.len() {
+        if state[n] == 0 {
+            visit(n, &edges, &mut state, &mut back);
+        }
+    }
+
+    let mut level = vec![0usize; graph.nodes.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, &(s, t)) in edges.iter().enumerate() {
+            if !back[i] && level[t] < level[s] + 1 {
+                level[t] = level[s] + 1;
+                changed = true;
+            }
+        }
+    }
+
+    let levels = level.iter().max().map(|m| m + 1).unwrap_or(0);
+    let mut members: Vec<Vec<usize>> = vec![vec![]; levels];
+    for (n, &l) in level.iter().enumerate() {
+        if graph.nodes[n].position.is_none() {
+            members[l].push(n);
+        }
+    }
+
+    // across is along a level, down is from one level to the next.
+    let size = |n: &Node| if graph.horizontal { (n.height, n.width) } else { (n.width, n.height) };
+    let widest = members
+        .iter()
+        .map(|m| {
+            m.iter().map(|&n| size(&graph.nodes[n]).0).sum::<f64>()
+                + graph.node_spacing * m.len().saturating_sub(1) as f64
+        })
+        .fold(0.0, f64::max);
+
+    let mut positions = vec![];
+    let mut down = 0.0;
+    let order: Vec<usize> = if graph.reversed { (0..levels).rev().collect() } else { (0..levels).collect() };
+    for l in order {
+        let nodes = &members[l];
+        let total: f64 = nodes.iter().map(|&n| size(&graph.nodes[n]).0).sum::<f64>()
+            + graph.node_spacing * nodes.len().saturating_sub(1) as f64;
+        let deepest = nodes.iter().map(|&n| size(&graph.nodes[n]).1).fold(0.0, f64::max);
+        let mut across = (widest - total) / 2.0;
+        for &n in nodes {
+            let (a, d) = size(&graph.nodes[n]);
+            let offset = (deepest - d) / 2.0;
+            positions.push((n, across, down + offset));
+            across += a + graph.node_spacing;
+        }
+        down += deepest + graph.level_spacing;
+    }
+
+    for (n, across, down) in positions {
+        let (x, y) = if graph.horizontal { (down, across) } else { (across, down) };
+        graph.nodes[n].position = Some((x + 20.0, y + 20.0));
+    }
+}
+
+fn model(graph: &Graph) -> String {
+    let mut xml = String::from(
+        "<mxGraphModel><root><mxCell id=\"0\"/><mxCell id=\"1\" parent=\"0\"/>",
+    );
+    for node in &graph.nodes {
+        let (x, y) = node.position.unwrap_or_default();
+        xml += &format!(
+            "<mxCell id=\"{}\" value=\"{}\" style=\"{}\" vertex=\"1\" parent=\"1\">\
+             <mxGeometry x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" as=\"geometry\"/></mxCell>",
+            escape(&node.id),
+            escape(&node.label),
+            escape(&node.style),
+            x,
+            y,
+            node.width,
+            node.height
+        );
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        xml += &format!(
+            "<mxCell id=\"edge-{}\" value=\"{}\" style=\"{}\" edge=\"1\" parent=\"1\" source=\"{}\" target=\"{}\">\
+             <mxGeometry relative=\"1\" as=\"geometry\"/></mxCell>",
+            i + 1,
+            escape(&edge.label),
+            escape(&edge.style),
+            escape(&edge.source),
+            escape(&edge.target)
+        );
+    }
+    xml += "</root></mxGraphModel>";
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mxfile::{absolute_bounds, cells};
+
+    #[test]
+    fn csv_test() {
+        let source = r##"
+## org chart
+# label: %name%<br>%position%
+# style: rounded=1;fillColor=%fill%;
+# identity: id
+# connect: {"from": "manager", "to": "id", "invert": true, "label": "manages"}
+# width: 120
+# height: 60
+id,name,position,manager,fill
+ceo,Ann,"CEO, founder",,#dae8fc
+cto,Bob,CTO,ceo,#d5e8d4
+dev,"Cy ""the dev""",Developer,cto,
+"##;
+        let model = csv(source).unwrap();
+        let cells = cells(&model);
+        let ann = cells.iter().find(|c| c.id == "ceo").unwrap();
+        assert_eq!(ann.label, "Ann<br>CEO, founder");
+        assert!(model.contains("fillColor=#dae8fc;"));
+        assert!(model.contains("value=\"Cy &quot;the dev&quot;&lt;br&gt;Developer\""));
+        assert!(model.contains("source=\"ceo\" target=\"cto\""));
+
+        // one level per management layer.
+        let bounds = absolute_bounds(&cells);
+        assert!(bounds["ceo"].y < bounds["cto"].y && bounds["cto"].y < bounds["dev"].y);
+        assert_eq!(bounds["dev"].width, 120.0);
+    }
+
+    #[test]
+    fn mermaid_test() {
+        let source = "
+flowchart LR
+    %% comment
+    A[Start] --> B{Is it?}
+    B -->|Yes| C(Done) & D((End))
+    B -. no .-> A
+";
+        let model = mermaid(source).unwrap();
+        let cells = cells(&model);
+        assert_eq!(cells.iter().find(|c| c.id == "B").unwrap().label, "Is it?");
+        assert!(model.contains("value=\"Yes\""));
+        assert!(model.contains("value=\"no\""));
+        assert!(model.contains("rhombus;"));
+        assert!(model.contains("dashed=1;"));
+
+        // left to right, the loop back to A doesn't move it.
+        let bounds = absolute_bounds(&cells);
+        assert!(bounds["A"].x < bounds["B"].x && bounds["B"].x < bounds["C"].x);
+        assert_eq!(bounds["C"].x, bounds["D"].x);
+
+        assert!(mermaid("sequenceDiagram\nA->>B: hi").is_err());
+
+        // `;` ends a statement, except inside a label.
+        let model = mermaid("graph TD; A[a; b] -->|c;d| B(\"e;f\"); B --> C").unwrap();
+        let nodes = crate::mxfile::cells(&model);
+        assert_eq!(nodes.iter().find(|c| c.id == "A").unwrap().label, "a; b");
+        assert_eq!(nodes.iter().find(|c| c.id == "B").unwrap().label, "e;f");
+        assert!(model.contains("value=\"c;d\""));
+        assert!(nodes.iter().any(|c| c.id == "C"));
+    }
+}
@@ -0,0 +1,146 @@
+use percent_encoding::utf8_percent_encode;
+
+use crate::mxfile;
+
+// html wrapped around inlined diagrams.
+
+// draw.io's viewer, it replaces the static svg inside `.mxgraph` elements
+// once loaded.
+pub const VIEWER_SCRIPT: &str = "<script type=\"text/javascript\" \
+    src=\"https://viewer.diagrams.net/js/viewer-static.min.js\" async></script>\n";
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// the diagram with its caption (html), `id` is what `{{#fig}}` links to.
+pub fn figure(id: &str, inner: &str, caption: &str) -> String {
+    let caption = match caption {
+        "" => String::new(),
+        c => format!("<figcaption>{}</figcaption>", c),
+    };
+    format!("<figure class=\"drawio\" id=\"{}\">{}{}</figure>", id, inner, caption)
+}
+
+/// the figures of every page of a diagram, by page name, followed by `after`
+/// (an edit link) and the link's caption. as tabs they are picked up by
+/// drawio-tabs.js, without the script they stay one below the other.
+pub fn pages(id: &str, figures: &[(String, String)], caption: &str, tabs: bool, after: &str) -> String {
+    let caption = match caption {
+        "" => String::new(),
+        c => format!("<figcaption>{}</figcaption>", escape(c)),
+    };
+    let (class, figures) = match tabs {
+        true => (
+            "drawio-pages drawio-tabs",
+            figures
+                .iter()
+                .map(|(page, figure)| {
+                    format!("<div class=\"drawio-tab\" data-page=\"{}\">{}</div>", escape(page), figure)
+                })
+                .collect::<String>(),
+        ),
+        false => ("drawio-pages", figures.iter().map(|(_, figure)| figure.as_str()).collect()),
+    };
+    format!("<figure class=\"{}\" id=\"{}\">{}{}{}</figure>", class, id, figures, after, caption)
+}
+
+/// a link below the diagram to where it can be edited.
+pub fn edit_link(href: &str, text: &str) -> String {
+    format!(
+        "<a class=\"drawio-edit\" href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a>",
+        escape(href),
+        escape(text)
+    )
+}
+
+/// opens the diagram in the draw.io web app, the diagram goes along in
+/// the url so nothing has to be published for it.
+pub fn drawio_url(xml: &str) -> String {
+    let compressed = mxfile::compress(xml);
+    format!("https://app.diagrams.net/#R{}", utf8_percent_encode(&compressed, mxfile::URI_COMPONENT))
+}
+
+/// opens the diagram file at `url` in the draw.io web app.
+pub fn drawio_open_url(url: &str) -> String {
+    format!("https://app.diagrams.net/#U{}", utf8_percent_encode(url, mxfile::URI_COMPONENT))
+}
+
+/// container picked up by drawio-zoom.js, without the script it is
+/// just the static diagram.
+pub fn zoom(inner: &str) -> String {
+    format!(
+        "<div class=\"drawio-zoom\"><div class=\"drawio-zoom-content\">{}</div></div>",
+        inner
+    )
+}
+
+/// embeds the diagram's xml for draw.io's viewer so layers and pages can be
+/// browsed, `inner` is shown until (or if) the viewer loads.
+pub fn drawio_viewer(inner: &str, xml: &str, page: usize) -> String {
+    let data = serde_json::json!({
+        "highlight": "#0000ff",
+        "nav": true,
+        "resize": true,
+        "toolbar": "zoom layers pages lightbox",
+        "page": page,
+        "xml": xml,
+    });
+    format!(
+        "<div class=\"mxgraph\" style=\"max-width: 100%;\" data-mxgraph=\"{}\">{}</div>",
+        escape(&data.to_string()),
+        inner
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_test() {
+        let figures = [
+            ("Now".to_string(), "<figure>a</figure>".to_string()),
+            ("Target".to_string(), "<figure>b</figure>".to_string()),
+        ];
+        assert_eq!(
+            pages("drawio-arch", &figures, "", false, ""),
+            "<figure class=\"drawio-pages\" id=\"drawio-arch\"><figure>a</figure><figure>b</figure></figure>"
+        );
+        let html = pages("drawio-arch", &figures, "Arch", true, "<a>edit</a>");
+        assert!(html.starts_with("<figure class=\"drawio-pages drawio-tabs\" id=\"drawio-arch\">"));
+        assert!(html.contains("<div class=\"drawio-tab\" data-page=\"Target\"><figure>b</figure></div>"));
+        assert!(html.ends_with("</div><a>edit</a><figcaption>Arch</figcaption></figure>"));
+    }
+
+    #[test]
+    fn edit_link_test() {
+        let url = drawio_url("<mxfile><diagram name=\"Page-1\"/></mxfile>");
+        let payload = url.strip_prefix("https://app.diagrams.net/#R").unwrap();
+        let compressed = percent_encoding::percent_decode_str(payload).decode_utf8().unwrap();
+        assert_eq!(
+            mxfile::decode(&compressed).unwrap(),
+            "<mxfile><diagram name=\"Page-1\"/></mxfile>"
+        );
+        assert_eq!(
+            drawio_open_url("https://github.com/acme/m/raw/HEAD/a b.drawio"),
+            "https://app.diagrams.net/#Uhttps%3A%2F%2Fgithub.com%2Facme%2Fm%2Fraw%2FHEAD%2Fa%20b.drawio"
+        );
+        assert_eq!(
+            edit_link("https://x/?a=1&b=2", "Edit"),
+            "<a class=\"drawio-edit\" href=\"https://x/?a=1&amp;b=2\" target=\"_blank\" rel=\"noopener\">Edit</a>"
+        );
+    }
+
+    #[test]
+    fn drawio_viewer_test() {
+        let html = drawio_viewer("<svg/>", r#"<mxfile a="1"/>"#, 2);
+        assert!(html.starts_with("<div class=\"mxgraph\""));
+        assert!(html.contains("&quot;page&quot;:2"));
+        assert!(html.contains("&lt;mxfile a=\\&quot;1\\&quot;/&gt;"));
+        assert!(html.ends_with("\"><svg/></div>"));
+    }
+}
@@ -2,6 +2,7 @@
 use mdbook::book::{Book, BookItem, Chapter};
 use mdbook::errors::Result;
 use mdbook::preprocess::{Preprocessor, PreprocessorContext};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::process;
 use std::time::Instant;
@@ -11,19 +12,46 @@ use regex::{Captures, Regex};
 use relative_path::RelativePathBuf;
 use std::path::{Path, PathBuf};
 
+mod assets;
+mod block;
+mod config;
 mod drawio_cache;
+mod figures;
+mod fonts;
+mod generate;
+mod html;
+mod link;
+mod mxfile;
+mod stats;
+mod svg;
+mod utilities;
+mod watch;
+
+pub use assets::install;
+pub use watch::watch;
+use config::{Config, EditLink, Numbering, Source, Viewer};
+use stats::BuildStats;
 
 // todo: add caching, each draw-io diagram can take awhile to render
 // so we should cache each draw-io diagram and check for modified date time.
 pub struct DrawIo {
     // draw io cache. 
     cache: drawio_cache::DrawIoCache,
+    // diagrams from code blocks are saved here to be exported like any other.
+    generated_dir: PathBuf,
+    // timings and counters for the current run.
+    stats: RefCell<BuildStats>,
+    // diagrams inlined in the current run.
+    figures: RefCell<Vec<figures::Figure>>,
 }
 
 impl DrawIo  {
     pub fn new<P: AsRef<Path>>(path: P) -> DrawIo {
         Self {
-            cache: drawio_cache::DrawIoCache::new(path),
+            cache: drawio_cache::DrawIoCache::new(&path),
+            generated_dir: path.as_ref().join("generated"),
+            stats: RefCell::new(BuildStats::default()),
+            figures: RefCell::new(vec![]),
         }
     }
 }
@@ -34,20 +62,52 @@ impl Preprocessor for DrawIo {
     }
 
     fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let config = Config::from_context(ctx)?;
+        let start = Instant::now();
+        self.stats.replace(BuildStats::default());
+        self.figures.replace(vec![]);
+        if config.edit_link == EditLink::Source && config.repository_url(Path::new(""), false).is_none() {
+            log::warn!("edit-link = \"source\" needs output.html's edit-url-template or git-repository-url");
+        }
+
         // anyway to determine
         let mut res = None;
-        book.for_each_mut(|item: &mut BookItem| {
+        // in reading order, for numbering figures through the book.
+        for_each_chapter(&mut book.sections, &mut |chapter| {
             if let Some(Err(ref f)) = res {
                 log::error!("Error on book! {:?}", f);
                 return;
             }
 
-            if let BookItem::Chapter(ref mut chapter) = *item {
-                res = Some(self.add_diagram(&ctx.root, chapter).map(|md| {
-                    chapter.content = md;
-                }));
+            res = Some(self.add_diagram(&ctx.root, &config, chapter).map(|md| {
+                chapter.content = md;
+            }));
+        });
+
+        // references can point forward, so only now all figures are known.
+        let figures = self.figures.borrow();
+        for_each_chapter(&mut book.sections, &mut |chapter| {
+            if let Some(path) = &chapter.path {
+                chapter.content = figures::replace_references(&chapter.content, &figures, path, &config);
             }
         });
+        drop(figures);
+
+        if let Some(title) = &config.list_of_figures {
+            let path = PathBuf::from(format!("{}.md", utilities::slug(title)));
+            let content = figures::list_of_figures(&self.figures.borrow(), title, &path, &config);
+            book.push_item(Chapter::new(title, content, path, vec![]));
+        }
+
+        let mut stats = self.stats.borrow_mut();
+        stats.total_ms = start.elapsed().as_secs_f64() * 1000.0;
+        log::info!("{}", stats.summary());
+        if let Some(stats_file) = &config.stats_file {
+            if let Err(e) = stats.write_json(ctx.root.join(stats_file)) {
+                log::error!("Failed to write stats to {}: {}", stats_file.display(), e);
+            }
+        }
+
         res.unwrap_or(Ok(())).map(|_| book)
     }
 
@@ -56,8 +116,40 @@ impl Preprocessor for DrawIo {
     }
 }
 
+// what the diagrams of a chapter share while it is processed.
+#[derive(Default)]
+struct ChapterState {
+    chapter_dir: PathBuf,
+    chapter_path: PathBuf,
+    // svg ids of the chapter start with it.
+    id_prefix: String,
+    // what the chapter's figures are numbered after, "3" for "Figure 3.1".
+    chapter_number: Option<String>,
+    // number of diagrams inlined so far.
+    instance: usize,
+    // the css switching light/dark variants only goes in once per chapter.
+    theme_style_added: bool,
+    viewer_script_added: bool,
+    // fonts whose @font-face is in the chapter already.
+    fonts_added: Vec<String>,
+    // how often each figure id was used, repeats get a suffix.
+    anchors: HashMap<String, usize>,
+}
+
+impl ChapterState {
+    // `anchor`, or `anchor-2` and so on when the chapter has it already.
+    fn unique_anchor(&mut self, anchor: String) -> String {
+        let count = self.anchors.entry(anchor.clone()).or_insert(0);
+        *count += 1;
+        match *count {
+            1 => anchor,
+            n => format!("{}-{}", anchor, n),
+        }
+    }
+}
+
 impl DrawIo {
-    fn add_diagram(&self, root_dir: &PathBuf, chapter: &mut Chapter) -> Result<String> {
+    fn add_diagram(&self, root_dir: &Path, config: &Config, chapter: &mut Chapter) -> Result<String> {
         // root points to the path of book.toml directory.
         log::info!("\n\nProcessing dir: {}", root_dir.to_str().unwrap());
         log::info!("Processing chapter: {}", chapter.name);
@@ -77,70 +169,743 @@ impl DrawIo {
             .clean();
 
         let mut new_content = String::new();
-        let regex_v = Regex::new(r"(!\[.*\])\((.*?)-(.*)(\.drawio)\)").unwrap();
         // start index of the chapter.content.
         // this keeps track of what content to keep,
         // so we can replace the link. 
         let mut start_index = 0;
+        let mut state = ChapterState {
+            chapter_dir: chapter_dir.clone(),
+            chapter_path: chapter_path.clone(),
+            // print.html puts every chapter in one page, so svg ids start
+            // with something unique to the chapter.
+            id_prefix: format!("drawio{}-", &utilities::hash(chapter_path.to_string_lossy().as_bytes())[..8]),
+            chapter_number: chapter
+                .number
+                .as_ref()
+                .map(|n| n.to_string().trim_end_matches('.').to_string()),
+            ..Default::default()
+        };
 
-        for entry in regex_v.captures_iter(&chapter.content) {
-            let m = entry.get(0).unwrap();
-            // named link in the md file [....]
-            let md_link = entry.get(1).unwrap().as_str();
-            let diagram_name = entry.get(2).unwrap().as_str();
-            let page_name = entry.get(3).unwrap().as_str();
-            let ext_name = entry.get(4).unwrap().as_str();
+        let mut entries = link::find_links(&chapter.content, &config.extensions, &chapter_dir);
+        for directive in link::find_directives(&chapter.content) {
+            match directive {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::error!(
+                    "Invalid {{{{#drawio}}}} in {}:{}: {}",
+                    Path::new("src").join(&chapter_path).display(),
+                    e.line,
+                    e.message
+                ),
+            }
+        }
+        for block in block::find_blocks(&chapter.content) {
+            // links that happen to be inside a block are part of it.
+            entries.retain(|e| e.range.end <= block.range.start || e.range.start >= block.range.end);
+            match self.generate(&block) {
+                Ok(entry) => entries.push(entry),
+                Err(f) => log::error!("Invalid diagram in {}: {}", chapter.name, f),
+            }
+        }
+        entries.sort_by_key(|e| e.range.start);
 
-            // todo: could have this get deteremined by option
-            let new_ext_name = ".svg";
-            let expected_key =
-                format!("{}-{}{}", diagram_name, page_name, new_ext_name)[2..].to_string();
-            let diagram_path = chapter_dir.join(format!("{}.drawio", diagram_name));
+        // a link to every page of a diagram is shown as one figure per page.
+        let groups: Vec<Vec<link::DiagramLink>> =
+            entries.into_iter().map(|e| expand_pages(e, &chapter_dir)).collect();
+        for group in groups {
+            let m = group[0].range.clone();
+            new_content += &chapter.content[start_index..m.start];
+            start_index = m.end;
+            let paged = group.len() > 1;
+            let tabs = group[0].options.tabs.unwrap_or(config.tabs);
+            let group_anchor = match &group[0].options.id {
+                Some(id) => format!("drawio-{}", utilities::slug(id)),
+                None => format!("drawio-{}", utilities::slug(&link::diagram_name(&group[0].path))),
+            };
+            let group_caption = group[0]
+                .options
+                .caption
+                .clone()
+                .or_else(|| group[0].title.clone())
+                .unwrap_or_else(|| group[0].alt.clone());
+            let first = group[0].clone();
+            let mut shown = vec![];
+            for entry in group {
+                let page_name = entry.options.page.clone().unwrap_or_default();
+                if let Some(figure) =
+                    self.show_page(config, chapter, &mut state, entry, paged, &group_caption, &mut new_content)
+                {
+                    shown.push((page_name, figure));
+                }
+            }
+            new_content += &match (shown.len(), paged) {
+                // the link is left as it was when nothing could be shown.
+                (0, _) => chapter.content[m].to_string(),
+                (_, true) => {
+                    let path = diagram_file(&first, &state.chapter_dir);
+                    let source_path = match first.generated {
+                        true => Path::new("src").join(&state.chapter_path),
+                        false => path.clone(),
+                    };
+                    let edit = edit_link(config, &path, &source_path, first.generated, None).unwrap_or_default();
+                    html::pages(&state.unique_anchor(group_anchor), &shown, &group_caption, tabs, &edit)
+                }
+                _ => shown.into_iter().map(|(_, figure)| figure).collect(),
+            };
+        }
+        new_content += &chapter.content[start_index..];
+        log::debug!("new content: \n{}", new_content);
+
+        Ok(new_content)
+    }
 
-            if !diagram_path.is_file() {
-                log::error!("Failed to find diagram: {}", diagram_path.to_str().unwrap());
-                // since the digrams path specified isn't available
-                // skip the entry. 
-                continue;
+    // shows a page of a diagram as a figure, None when it can't be. the css
+    // and scripts it needs go into `new_content` ahead of it. `paged` when
+    // it is one of several pages from the same link.
+    #[allow(clippy::too_many_arguments)]
+    fn show_page(
+        &self,
+        config: &Config,
+        chapter: &Chapter,
+        state: &mut ChapterState,
+        mut entry: link::DiagramLink,
+        paged: bool,
+        group_caption: &str,
+        new_content: &mut String,
+    ) -> Option<String> {
+        entry.options.apply_defaults(config);
+        // a diagram can have a theme of its own.
+        let theme = entry.options.theme.unwrap_or(config.theme);
+        let mut diagram_path = diagram_file(&entry, &state.chapter_dir);
+        // where links inside the diagram are relative to.
+        let link_dir = if entry.generated {
+            state.chapter_dir.clone()
+        } else {
+            diagram_path.parent().unwrap().to_path_buf()
+        };
+        // the file readers are sent to by edit links, the chapter for
+        // code blocks.
+        let source_path = if entry.generated {
+            Path::new("src").join(&state.chapter_path)
+        } else {
+            diagram_path.clone()
+        };
+        // the diagram may be extracted into another file below, anchors
+        // keep the name of the linked one.
+        let diagram_name = link::diagram_name(&diagram_path);
+
+        let timer = Instant::now();
+        let mut stats = self.stats.borrow_mut();
+        let diagram_stats = stats.diagram(&diagram_path);
+        diagram_stats.references += 1;
+
+        if !diagram_path.is_file() {
+            log::error!("Failed to find diagram: {}", diagram_path.to_str().unwrap());
+            diagram_stats.failures += 1;
+            diagram_stats.add_time(timer.elapsed());
+            // since the digrams path specified isn't available
+            // skip the entry. 
+            return None;
+        }
+
+        // editable images are shown as they are, unless the link has
+        // options, then the embedded diagram is exported.
+        let as_is = link::is_image(&diagram_path) && entry.options.only_placement();
+        if link::is_image(&diagram_path) && !as_is {
+            let extracted = mxfile::read(&diagram_path).and_then(|xml| {
+                let first = mxfile::pages(&xml).into_iter().next().ok_or("diagram has no pages")?;
+                entry.options.page.get_or_insert(first.name);
+                self.save_generated(&diagram_name, &xml)
+            });
+            match extracted {
+                Ok(path) => diagram_path = path,
+                Err(f) => {
+                    log::error!("Failed to read the diagram in {}: {}", diagram_path.to_str().unwrap(), f);
+                    diagram_stats.failures += 1;
+                    diagram_stats.add_time(timer.elapsed());
+                    return None;
+                }
+            }
+        }
+
+        let page_name = match &entry.options.page {
+            Some(p) => p.as_str(),
+            None if as_is => "",
+            None => {
+                log::error!("No page given for diagram: {}", diagram_path.to_str().unwrap());
+                diagram_stats.failures += 1;
+                diagram_stats.add_time(timer.elapsed());
+                return None;
             }
+        };
+
+        let new_diagrams = if as_is {
+            Ok(std::fs::read_to_string(&diagram_path).unwrap_or_default())
+        } else {
+            self.export_diagram(&diagram_path, &entry.options, config, diagram_stats)
+        };
+        diagram_stats.add_time(timer.elapsed());
 
-            let f = self.cache.get_diagram(&diagram_path, &expected_key);
-            let new_diagrams = match f {
-                Ok(r) => { r },
+        let new_diagrams = match new_diagrams {
+            Ok(r) => r,
+            Err(f) => {
+                log::error!("Failed to export page {} of {}: {}",
+                            page_name, diagram_path.to_str().unwrap(), f);
+                diagram_stats.failures += 1;
+                return None;
+            }
+        };
+
+        let exported_png = entry.options.format == link::Format::Png && !as_is;
+        let new_diagrams = if entry.options.crops() && exported_png {
+            log::warn!("Only svg diagrams can be cropped: {}", diagram_path.to_str().unwrap());
+            new_diagrams
+        } else if entry.options.crops() {
+            match crop(&diagram_path, &entry.options, config, &new_diagrams) {
+                Ok(cropped) => cropped,
                 Err(f) => {
-                    let new_diagrams = get_content_from_diagram(&diagram_path).unwrap();
-                    for (key, value) in new_diagrams.into_iter() {
-                        log::debug!("diagrams: {}", key);
-                        self.cache.add_diagram(&diagram_path, &key, &value);
-                    }
-                    self.cache.get_diagram(&diagram_path,
-                                           &expected_key).unwrap()
+                    log::error!("Failed to crop page {} of {}: {}",
+                                page_name, diagram_path.to_str().unwrap(), f);
+                    new_diagrams
                 }
+            }
+        } else {
+            new_diagrams
+        };
+
+        // png files are linked as they are, exported pages embedded.
+        let png_file = as_is && diagram_path.extension().is_some_and(|e| e.eq_ignore_ascii_case("png"));
+        let converted = if config.text_to_path && !exported_png && !png_file {
+            self.text_to_path(&new_diagrams, config)
+                .map_err(|f| log::error!("Failed to turn text into paths in {}: {}", diagram_path.display(), f))
+                .ok()
+        } else {
+            None
+        };
+        // text left as text needs the fonts.
+        let text_as_paths = converted.is_some();
+        let new_diagrams = converted.unwrap_or(new_diagrams);
+        if !exported_png && !text_as_paths {
+            let fonts: Vec<&config::Font> = fonts::used(&new_diagrams, &config.fonts)
+                .into_iter()
+                .filter(|f| !state.fonts_added.contains(&f.family))
+                .collect();
+            *new_content += &fonts::font_faces(&fonts);
+            state.fonts_added.extend(fonts.iter().map(|f| f.family.clone()));
+        }
+
+        if theme == config::Theme::LightDark && !state.theme_style_added {
+            *new_content += &svg::theme_style();
+            state.theme_style_added = true;
+        }
+
+        state.instance += 1;
+        let anchor = state.unique_anchor(match &entry.options.id {
+            Some(id) if paged => page_anchor(id, page_name),
+            Some(id) => format!("drawio-{}", utilities::slug(id)),
+            None => page_anchor(&diagram_name, page_name),
+        });
+        let number = match (config.figure_numbering, &state.chapter_number) {
+            (Numbering::None, _) => None,
+            (Numbering::Chapter, Some(n)) => Some(format!("{}.{}", n, state.instance)),
+            // unnumbered chapters would all start at 1 again.
+            (Numbering::Chapter, None) | (Numbering::Book, _) => {
+                Some((self.figures.borrow().len() + 1).to_string())
+            }
+        };
+        let thumbnail = if config.list_of_figures.is_none() {
+            String::new()
+        } else if png_file {
+            let src = diagram_path.strip_prefix("src").unwrap_or(&diagram_path);
+            format!("<img src=\"{}\" alt=\"\">", src.to_string_lossy())
+        } else if exported_png {
+            format!("<img src=\"data:image/png;base64,{}\" alt=\"\">", new_diagrams)
+        } else {
+            let svg = svg::strip_prolog(&new_diagrams).unwrap_or_default();
+            svg::rewrite_links(&svg, |href| resolve_link(href, &diagram_path, &link_dir, Path::new("src")))
+        };
+        let figure = figures::Figure {
+            diagram: diagram_path.clone(),
+            page: page_name.to_string(),
+            // the option, the link's title or its alt text. every
+            // page of a diagram is captioned with its name instead.
+            caption: match paged {
+                true => page_name.to_string(),
+                false => group_caption.to_string(),
+            },
+            number,
+            // where it is rendered, README.md is index.md by now.
+            chapter: chapter.path.clone().unwrap_or_else(|| state.chapter_path.clone()),
+            chapter_name: chapter.name.clone(),
+            anchor,
+            thumbnail,
+        };
+
+        let mut body = if png_file || exported_png {
+            let src = if png_file {
+                entry.path.clone()
+            } else {
+                format!("data:image/png;base64,{}", new_diagrams)
+            };
+            let natural = if png_file {
+                std::fs::read(&diagram_path).ok()
+            } else {
+                base64::decode(&new_diagrams).ok()
+            };
+            let (width, height) = entry.options.size(natural.as_deref().and_then(svg::png_size));
+            let size = |name, value: Option<f64>| {
+                value.map(|v| format!(" {}=\"{}\"", name, v)).unwrap_or_default()
             };
+            let mut style = format!(
+                "max-width: {}; height: auto",
+                entry.options.max_width.as_deref().unwrap_or("100%")
+            );
+            if let Some(align) = entry.options.align {
+                style = format!("{}; {}", style, align.style());
+            }
+            format!(
+                "<img src=\"{}\" alt=\"{}\"{}{} style=\"{}\">",
+                src,
+                html::escape(&entry.alt),
+                size("width", width),
+                size("height", height),
+                style
+            )
+        } else {
+            let new_diagrams = svg::strip_prolog(&new_diagrams).unwrap_or(new_diagrams);
+            let new_diagrams = svg::rewrite_links(&new_diagrams, |href| {
+                resolve_link(href, &diagram_path, &link_dir, &state.chapter_dir)
+            });
+            // before the light and dark copies are made.
+            let new_diagrams = svg::set_size(&new_diagrams, &entry.options);
+            svg::process(&new_diagrams, config, theme, &format!("{}{}-", state.id_prefix, state.instance))
+        };
+        match config.viewer {
+            Viewer::Static => {},
+            Viewer::Zoom => body = html::zoom(&body),
+            Viewer::Drawio => {
+                if let Ok(xml) = mxfile::read(&diagram_path) {
+                    let page = mxfile::pages(&xml)
+                        .iter()
+                        .position(|p| p.name == page_name)
+                        .unwrap_or(0);
+                    if !state.viewer_script_added {
+                        *new_content += html::VIEWER_SCRIPT;
+                        state.viewer_script_added = true;
+                    }
+                    body = html::drawio_viewer(&body, &xml, page);
+                }
+            },
+        }
+        // the pages of a link share one, below them all.
+        if !paged {
+            let page = Some(page_name).filter(|p| !p.is_empty());
+            body += &edit_link(config, &diagram_path, &source_path, entry.generated, page).unwrap_or_default();
+        }
+        let shown = html::figure(&figure.anchor, &body, &figure.caption_html(config));
+        self.figures.borrow_mut().push(figure);
+        Some(shown)
+    }
 
-            new_content += &chapter.content[start_index..m.start()];
-            new_content += &new_diagrams;
-            start_index = m.end();
+    // saves the diagram of a code block, generated first for csv and
+    // mermaid sources. named by its content so unchanged blocks are found
+    // in the cache.
+    fn generate(&self, block: &block::CodeBlock) -> Result<link::DiagramLink, String> {
+        let xml = match block.kind {
+            block::BlockKind::Drawio => mxfile::from_source(&block.source)?,
+            block::BlockKind::Csv => mxfile::from_source(&generate::csv(&block.source)?)?,
+            block::BlockKind::Mermaid => mxfile::from_source(&generate::mermaid(&block.source)?)?,
+        };
+        let mut options = block.options.clone();
+        if options.page.is_none() {
+            let first = mxfile::pages(&xml).into_iter().next().ok_or("diagram has no pages")?;
+            options.page = Some(first.name);
         }
-        new_content += &chapter.content[start_index..];
-        log::debug!("new content: \n{}", new_content);
+        let path = self.save_generated(&utilities::hash(xml.as_bytes()), &xml)?;
+        Ok(link::DiagramLink {
+            range: block.range.clone(),
+            alt: String::new(),
+            title: None,
+            path: path.to_string_lossy().into_owned(),
+            options,
+            generated: true,
+        })
+    }
 
-        Ok(new_content)
+    // writes a diagram that has no file of its own, the path returned is
+    // relative to the book as the cache wants it. the file is named `name`
+    // so exported pages and anchors are too.
+    fn save_generated(&self, name: &str, xml: &str) -> Result<PathBuf, String> {
+        let dir = self.generated_dir.join(utilities::hash(xml.as_bytes()));
+        let path = dir.join(format!("{}.drawio", name));
+        if !path.is_file() {
+            std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+            std::fs::write(&path, xml).map_err(|e| e.to_string())?;
+        }
+        utilities::relative_path(&path, ".").map_err(|e| e.to_string())
+    }
+
+    // the page the options ask for, from the cache or exported.
+    fn export_diagram(
+        &self,
+        diagram_path: &Path,
+        options: &link::DiagramOptions,
+        config: &Config,
+        diagram_stats: &mut stats::DiagramStats,
+    ) -> Result<String, String> {
+        let page_name = options.page.as_deref().unwrap_or_default();
+        let diagram_stem = diagram_path.file_stem().unwrap().to_str().unwrap();
+        // the exporter names each page <diagram>-<page>.svg
+        let expected_key = format!("{}-{}.{}", diagram_stem, page_name, options.format.extension());
+        let (page, edited) = edit_page(diagram_path, options, config)?
+            .ok_or_else(|| format!("diagram has no page {}", page_name))?;
+        // pages are cached by their content, edits included, so only the
+        // pages that changed get exported again.
+        let cache_key = page_key(diagram_stem, &page, edited.as_ref(), options.format);
+
+        if let Some(r) = self.cache.get_page(diagram_path, &cache_key) {
+            diagram_stats.cache_hits += 1;
+            return Ok(r);
+        }
+        diagram_stats.cache_misses += 1;
+        diagram_stats.exports += 1;
+        if let Some(edited) = edited {
+            return self.export_page(diagram_path, edited, options.format, &expected_key, &cache_key);
+        }
+
+        self.export_pages(diagram_path, options.format)?;
+        self.cache
+            .get_page(diagram_path, &cache_key)
+            .ok_or_else(|| "exported diagram is missing the page".to_string())
+    }
+
+    /// exports the pages of the diagram that aren't cached yet, returns how
+    /// many. paths are relative to the book root.
+    pub(crate) fn export_pages(&self, diagram_path: &Path, format: link::Format) -> Result<usize, String> {
+        let diagram_stem = diagram_path.file_stem().unwrap().to_str().unwrap();
+        let pages = mxfile::read_pages(diagram_path)?;
+        let missing: Vec<&mxfile::Page> = pages
+            .iter()
+            .filter(|p| self.cache.get_page(diagram_path, &page_key(diagram_stem, p, None, format)).is_none())
+            .collect();
+
+        // starting the exporter takes longer than exporting a page, a run
+        // per page only pays off for less than half of them.
+        if missing.len() * 2 < pages.len() {
+            for page in &missing {
+                let expected_key = format!("{}-{}.{}", diagram_stem, page.name, format.extension());
+                let cache_key = page_key(diagram_stem, page, None, format);
+                self.export_page(diagram_path, (*page).clone(), format, &expected_key, &cache_key)?;
+            }
+            return Ok(missing.len());
+        }
+
+        let new_diagrams = if diagram_path.extension().is_some_and(|e| e == "drawio") {
+            export_file(diagram_path, format)?
+        } else {
+            // the exporter only picks up .drawio files, a copy is exported.
+            let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+            let temp_path = temp_dir.path().join(format!("{}.drawio", diagram_stem));
+            std::fs::write(&temp_path, mxfile::read(diagram_path)?).map_err(|e| e.to_string())?;
+            export_file(&temp_path, format)?
+        };
+        for page in &pages {
+            let expected_key = format!("{}-{}.{}", diagram_stem, page.name, format.extension());
+            match new_diagrams.get(&expected_key) {
+                Some(value) => {
+                    log::debug!("diagrams: {}", expected_key);
+                    self.cache.add_page(diagram_path, &page_key(diagram_stem, page, None, format), value);
+                }
+                None => log::error!("The export of {} is missing page {}", diagram_path.display(), page.name),
+            }
+        }
+        Ok(pages.len())
+    }
+
+    // the svg with its text turned into paths, cached by content since the
+    // tool is about as slow as the export.
+    fn text_to_path(&self, svg: &str, config: &Config) -> Result<String, String> {
+        let key = format!(
+            "{}.svg",
+            utilities::hash(format!("{}\n{}", config.text_to_path_command, svg).as_bytes())
+        );
+        if let Some(cached) = self.cache.get_page("text-to-path", &key) {
+            return Ok(cached);
+        }
+        let converted = fonts::text_to_path(svg, &config.text_to_path_command)?;
+        let converted = svg::strip_prolog(&converted).unwrap_or(converted);
+        self.cache.add_diagram("text-to-path", &key, &converted)?;
+        Ok(converted)
+    }
+
+    // exports a single (edited) page on its own, the result is cached
+    // under `cache_key`.
+    fn export_page(
+        &self,
+        diagram_path: &Path,
+        page: mxfile::Page,
+        format: link::Format,
+        expected_key: &str,
+        cache_key: &str,
+    ) -> Result<String, String> {
+        // same name so the exporter names the page as usual.
+        let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        let stem = diagram_path.file_stem().unwrap().to_str().unwrap();
+        let temp_path = temp_dir.path().join(format!("{}.drawio", stem));
+        std::fs::write(&temp_path, mxfile::write(&[page])).map_err(|e| e.to_string())?;
+
+        let exported = export_file(&temp_path, format)?;
+        let svg = exported
+            .get(expected_key)
+            .ok_or_else(|| "exported diagram is missing the page".to_string())?;
+        self.cache.add_page(diagram_path, cache_key, svg);
+        Ok(svg.clone())
+    }
+}
+
+// calls `f` with every chapter, parents before their sub chapters unlike
+// `Book::for_each_mut`.
+fn for_each_chapter<F: FnMut(&mut Chapter)>(items: &mut [BookItem], f: &mut F) {
+    for item in items {
+        if let BookItem::Chapter(chapter) = item {
+            f(chapter);
+            for_each_chapter(&mut chapter.sub_items, f);
+        }
     }
 }
 
-/// Contains the names of draw io diagrams that have been converted
-/// to avoid reconvering if a link is used multiple times.
+// applies the changes a link asks for to its page: hiding layers,
+// highlighting cells and filling in placeholders. the page along with the
+// edited one if it changed, None when there is no such page.
+fn edit_page(
+    diagram_path: &Path,
+    options: &link::DiagramOptions,
+    config: &Config,
+) -> Result<Option<(mxfile::Page, Option<mxfile::Page>)>, String> {
+    let page_name = options.page.as_deref().unwrap_or_default();
+    let xml = mxfile::read(diagram_path)?;
+    let page = match mxfile::pages(&xml).into_iter().find(|p| p.name == page_name) {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+
+    let original = mxfile::decode(&page.content)?;
+    let mut model = mxfile::substitute(&original, |name| config.variable(name));
+    if let Some(layers) = &options.layers {
+        model = mxfile::show_layers(&model, layers);
+    }
+    if let Some(cells) = &options.highlight {
+        model = mxfile::highlight(&model, cells, &options.highlight_style());
+    }
+
+    // substitute writes every tag back out, `<a />` as `<a/>` and so on,
+    // so the page only changed if it differs from that.
+    if model == svg::rewrite_tags(&original, |_, _| {}) {
+        return Ok(Some((page, None)));
+    }
+    let edited = mxfile::Page {
+        content: model,
+        ..page.clone()
+    };
+    Ok(Some((page, Some(edited))))
+}
+
+// where an exported page is cached, under a hash of its content and one of
+// the edited page when a link changes it. all of a page's entries are under
+// the hash of the page, so those of its old content can be found.
+fn page_key(
+    diagram_stem: &str,
+    page: &mxfile::Page,
+    edited: Option<&mxfile::Page>,
+    format: link::Format,
+) -> String {
+    let name = format!("{}-{}.{}", diagram_stem, page.name, format.extension());
+    match edited {
+        Some(edited) => format!(
+            "{}/{}/{}",
+            utilities::hash(page.content.as_bytes()),
+            utilities::hash(edited.content.as_bytes()),
+            name
+        ),
+        None => format!("{}/{}", utilities::hash(page.content.as_bytes()), name),
+    }
+}
+
+// space kept around cropped cells when no margin is given.
+const DEFAULT_CROP_MARGIN: f64 = 20.0;
+
+// cuts the exported page down to the cells or region from the options.
+fn crop(diagram_path: &Path, options: &link::DiagramOptions, config: &Config, svg: &str) -> Result<String, String> {
+    let page_name = options.page.as_deref().unwrap_or_default();
+    // the page as it was exported, without the layers it hides.
+    let page = match edit_page(diagram_path, options, config)? {
+        Some((page, edited)) => edited.unwrap_or(page),
+        None => return Err(format!("no page named {}", page_name)),
+    };
+    let cells = mxfile::cells(&mxfile::decode(&page.content)?);
+    let bounds = mxfile::absolute_bounds(&cells);
+    let shown = mxfile::shown(&cells);
+
+    // an approximation of what the exporter measures, labels sticking
+    // out of their shapes are not accounted for.
+    let page_bounds = bounds
+        .iter()
+        .filter(|(id, _)| shown.contains(*id))
+        .map(|(_, rect)| *rect)
+        .reduce(|a, b| a.union(&b))
+        .ok_or("page is empty")?;
+
+    let region = match (&options.region, &options.cells) {
+        (Some(region), _) => region.grow(options.margin.unwrap_or(0.0)),
+        (None, Some(wanted)) => cells
+            .iter()
+            .filter(|c| wanted.contains(&c.id) || wanted.contains(&c.label))
+            .filter_map(|c| bounds.get(&c.id).copied())
+            .reduce(|a, b| a.union(&b))
+            .ok_or_else(|| format!("none of the cells {} found", wanted.join(",")))?
+            .grow(options.margin.unwrap_or(DEFAULT_CROP_MARGIN)),
+        (None, None) => return Ok(svg.to_string()),
+    };
+    Ok(svg::crop(svg, &region, &page_bounds))
+}
+
+// `arch.drawio` (no page) and `arch.drawio#all` show every page in the
+// diagram, an entry for each. editable images and code blocks keep to their
+// first page unless asked.
+fn expand_pages(entry: link::DiagramLink, chapter_dir: &Path) -> Vec<link::DiagramLink> {
+    let all = entry.options.all_pages
+        || (entry.options.page.is_none() && !entry.generated && !link::is_image(&entry.path));
+    if !all {
+        return vec![entry];
+    }
+    // errors are reported when the entry is shown.
+    let pages = match mxfile::read(diagram_file(&entry, chapter_dir)) {
+        Ok(xml) => mxfile::pages(&xml),
+        Err(_) => return vec![entry],
+    };
+    if pages.is_empty() {
+        return vec![entry];
+    }
+    pages
+        .into_iter()
+        .map(|page| {
+            let mut entry = entry.clone();
+            entry.options.page = Some(page.name);
+            entry
+        })
+        .collect()
+}
+
+// the link below a diagram to edit it, if there is one. `source_path` is
+// the file in the repository. when the diagram goes along in the link only
+// `page` does, or every page without one.
+fn edit_link(
+    config: &Config,
+    diagram_path: &Path,
+    source_path: &Path,
+    generated: bool,
+    page: Option<&str>,
+) -> Option<String> {
+    let url = match (config.edit_link, config.repository_url(source_path, config.edit_link == EditLink::Drawio)) {
+        (EditLink::None, _) | (EditLink::Source, None) => return None,
+        (EditLink::Source, Some(url)) => return Some(html::edit_link(&url, "Edit this diagram")),
+        (EditLink::Drawio, Some(url)) if !generated => html::drawio_open_url(&url),
+        // a copy of the diagram in every link would undo stripping it.
+        (EditLink::Drawio, _) if config.source == Source::Strip => return None,
+        (EditLink::Drawio, _) => {
+            let xml = mxfile::read(diagram_path).and_then(|xml| match page {
+                Some(page) => mxfile::pages(&xml)
+                    .into_iter()
+                    .find(|p| p.name == page)
+                    .map(|p| mxfile::write(&[p]))
+                    .ok_or_else(|| format!("no page named {}", page)),
+                None => Ok(xml),
+            });
+            match xml {
+                Ok(xml) => html::drawio_url(&xml),
+                Err(f) => {
+                    log::warn!("No edit link for {}: {}", diagram_path.display(), f);
+                    return None;
+                }
+            }
+        }
+    };
+    Some(html::edit_link(&url, "Edit in draw.io"))
+}
+
+// the file of a diagram relative to the book, code blocks are saved there
+// already.
+fn diagram_file(entry: &link::DiagramLink, chapter_dir: &Path) -> PathBuf {
+    if entry.generated {
+        PathBuf::from(&entry.path)
+    } else {
+        chapter_dir.join(&entry.path).clean()
+    }
+}
+
+// id of the element an inlined diagram page is wrapped in.
+fn page_anchor(diagram_stem: &str, page_name: &str) -> String {
+    match page_name {
+        // editable images shown as they are.
+        "" => format!("drawio-{}", utilities::slug(diagram_stem)),
+        _ => format!("drawio-{}-{}", utilities::slug(diagram_stem), utilities::slug(page_name)),
+    }
+}
+
+// draw.io cells can link to a chapter (relative to `link_dir`, or to the
+// book's src dir when starting with '/') or to another page of the same
+// diagram. the new target, if the link needs rewriting for the book.
+fn resolve_link(
+    href: &str,
+    diagram_path: &Path,
+    link_dir: &Path,
+    chapter_dir: &Path,
+) -> Option<String> {
+    if let Some(page_id) = href.strip_prefix("data:page/id,") {
+        let pages = mxfile::read_pages(diagram_path).ok()?;
+        let page = pages.iter().find(|p| p.id == page_id)?;
+        return Some(format!("#{}", page_anchor(&link::diagram_name(diagram_path), &page.name)));
+    }
+
+    let (path, fragment) = match href.find('#') {
+        Some(i) => href.split_at(i),
+        None => (href, ""),
+    };
+    if !path.ends_with(".md") || path.contains("://") {
+        return None;
+    }
+
+    let target = match path.strip_prefix('/') {
+        Some(p) => PathBuf::from("src").join(p),
+        None => link_dir.join(path),
+    };
+    // mdbook renders README.md as the index of its directory.
+    let target = if target.file_name()? == "README.md" {
+        target.with_file_name("index.html")
+    } else {
+        target.with_extension("html")
+    };
+    let relative = utilities::relative_path(target, chapter_dir).ok()?;
+    Some(format!("{}{}", relative.to_str()?, fragment))
+}
 
 // pulls out the svg image from a draw io exported xml file.
 fn extract_svg<P: AsRef<Path>>(drawio_svg_path: P) -> Option<String> {
     let string = std::fs::read_to_string(drawio_svg_path).unwrap();
-    let p = string.find("<svg ").unwrap();
-    Some(string[p..].to_owned())
+    // the same page always gives the same svg.
+    svg::strip_prolog(&string).map(|s| svg::normalize(&s))
 }
 
 fn get_content_from_diagram<P: AsRef<Path>>(
     diagram_path: P,
+) -> Result<HashMap<String, String>, &'static str> {
+    export_file(diagram_path, link::Format::Svg)
+}
+
+// exports every page of the diagram, keyed by the file the exporter wrote.
+// png pages are kept base64 encoded.
+fn export_file<P: AsRef<Path>>(
+    diagram_path: P,
+    format: link::Format,
 ) -> Result<HashMap<String, String>, &'static str> {
     // assert diagram exists.
 
@@ -154,7 +919,7 @@ fn get_content_from_diagram<P: AsRef<Path>>(
         "--output",
         temp_dir.path().to_str().unwrap(),
         "--format",
-        "svg",
+        format.extension(),
         "--output-mode",
         "absolute",
     ];
@@ -162,7 +927,7 @@ fn get_content_from_diagram<P: AsRef<Path>>(
 
     let mut results = HashMap::new();
     let output = process::Command::new("drawio-exporter.exe")
-        .args(&args)
+        .args(args)
         .output();
     match output {
         Ok(r) => {
@@ -173,7 +938,14 @@ fn get_content_from_diagram<P: AsRef<Path>>(
                             e.path().file_name().unwrap().to_str().unwrap().to_string();
 
                         log::debug!("Converted {}", filename);
-                        results.insert(filename, extract_svg(e.path()).unwrap());
+                        let content = match format {
+                            link::Format::Svg => extract_svg(e.path()),
+                            link::Format::Png => std::fs::read(e.path()).ok().map(base64::encode),
+                        };
+                        match content {
+                            Some(content) => { results.insert(filename, content); },
+                            None => log::error!("No {} found in {}", format.extension(), filename),
+                        }
                     } else {
                         log::debug!("Is not a file: {}", e.path().to_str().unwrap());
                     }
@@ -188,9 +960,7 @@ fn get_content_from_diagram<P: AsRef<Path>>(
         }
         Err(f) => {
             log::error!("Failed conversion: {:?}", f);
-            // todo: how to return an error?????
-            log::error!("purpose to make error");
-            panic!()
+            return Err("failed to run drawio-exporter.exe");
         }
     }
 
@@ -247,5 +1017,152 @@ blkafjaklfj
         assert!(false)
     }
 
+    #[test]
+    fn resolve_link_test() {
+        let diagram_path = PathBuf::from("src/arch/overview.drawio");
+        let link_dir = PathBuf::from("src/arch");
+        let chapter_dir = PathBuf::from("src/guide");
+
+        assert_eq!(
+            resolve_link("components/db.md#schema", &diagram_path, &link_dir, &chapter_dir),
+            Some("../arch/components/db.html#schema".to_string())
+        );
+        assert_eq!(
+            resolve_link("/README.md", &diagram_path, &link_dir, &chapter_dir),
+            Some("../index.html".to_string())
+        );
+        assert_eq!(resolve_link("https://example.com/a.md", &diagram_path, &link_dir, &chapter_dir), None);
+        assert_eq!(resolve_link("db.html", &diagram_path, &link_dir, &chapter_dir), None);
+
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        assert_eq!(
+            resolve_link(
+                "data:page/id,-B5ofvQMc2Ju6WmLbymv",
+                &resources_dir.join("testdiagram.drawio"),
+                &resources_dir,
+                &chapter_dir
+            ),
+            Some("#drawio-testdiagram-page-1".to_string())
+        );
+    }
+
+    #[test]
+    fn edit_page_test() {
+        // saved by draw.io, `<mxCell id="0" />` and all.
+        let diagram_path = Path::new("resources/twopages.drawio");
+        let config = Config::default();
+        let (page, edited) = edit_page(diagram_path, &link::DiagramOptions::parse("page=One"), &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.name, "One");
+        assert_eq!(edited, None);
+        let (_, edited) = edit_page(diagram_path, &link::DiagramOptions::parse("page=One&highlight=a"), &config)
+            .unwrap()
+            .unwrap();
+        assert!(edited.unwrap().content.contains("strokeColor="));
+        assert_eq!(edit_page(diagram_path, &link::DiagramOptions::parse("page=Three"), &config).unwrap(), None);
+    }
+
+    #[test]
+    fn page_key_test() {
+        let page = |content: &str| mxfile::Page {
+            id: "p1".to_string(),
+            name: "Net".to_string(),
+            content: content.to_string(),
+        };
+        let key = page_key("arch", &page("<mxGraphModel/>"), None, link::Format::Svg);
+        assert!(key.ends_with("/arch-Net.svg"));
+        assert_eq!(key, page_key("arch", &page("<mxGraphModel/>"), None, link::Format::Svg));
+        let changed = page_key("arch", &page("<mxGraphModel a=\"1\"/>"), None, link::Format::Svg);
+        assert_ne!(key, changed);
+        let edit = page("<mxGraphModel b=\"1\"/>");
+        let edited = page_key("arch", &page("<mxGraphModel/>"), Some(&edit), link::Format::Svg);
+        assert_eq!(edited.split('/').next(), key.split('/').next());
 
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = drawio_cache::DrawIoCache::new(temp_dir.path());
+        assert_eq!(cache.get_page("src/arch.drawio", &key), None);
+        cache.add_page("src/arch.drawio", &key, "<svg/>").unwrap();
+        cache.add_page("src/arch.drawio", &edited, "<svg/>").unwrap();
+        assert_eq!(cache.get_page("src/arch.drawio", &key).as_deref(), Some("<svg/>"));
+        // the page changed, what was exported from the old content goes.
+        cache.add_page("src/arch.drawio", &changed, "<svg a/>").unwrap();
+        assert_eq!(cache.get_page("src/arch.drawio", &key), None);
+        assert_eq!(cache.get_page("src/arch.drawio", &edited), None);
+        assert!(!temp_dir.path().join("src/arch.drawio").join(key.split('/').next().unwrap()).exists());
+    }
+
+    #[test]
+    fn expand_pages_test() {
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let entry = |path: &str| {
+            link::find_links(&format!("![a]({})", path), &["drawio".to_string()], &resources_dir).remove(0)
+        };
+
+        let pages = expand_pages(entry("twopages.drawio#all&width=300"), &resources_dir);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].options.page.as_deref(), Some("Two"));
+        assert_eq!(pages[1].options.width, Some(300.0));
+        let pages = expand_pages(entry("testdiagram.drawio"), &resources_dir);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].options.page.as_deref(), Some("Page-1"));
+        // code blocks are saved relative to the book.
+        let mut block = entry("resources/twopages.drawio#all");
+        block.generated = true;
+        assert_eq!(expand_pages(block, &resources_dir).len(), 2);
+        // a page was given, or the diagram can't be read.
+        assert_eq!(expand_pages(entry("testdiagram-Page-1.drawio"), &resources_dir).len(), 1);
+        assert_eq!(expand_pages(entry("missing.drawio"), &resources_dir)[0].options.page, None);
+    }
+
+    #[test]
+    fn add_diagram_pages_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let drawio = DrawIo::new(temp_dir.path());
+        // exported already, as a whole like `export_pages` does, so the
+        // exporter isn't needed.
+        let diagram_path = Path::new("resources/twopages.drawio");
+        for page in mxfile::read_pages(diagram_path).unwrap() {
+            let svg = format!("<svg width=\"10px\" height=\"10px\"><text>{}</text></svg>", page.name);
+            let key = page_key("twopages", &page, None, link::Format::Svg);
+            drawio.cache.add_page(diagram_path, &key, &svg).unwrap();
+        }
+
+        let markdown = "![a](twopages.drawio \"Request flow\")\n![b](twopages.drawio#page=Two)\n";
+        let mut chapter = Chapter::new("Pages", markdown.to_string(), "../resources/pages.md", vec![]);
+        let content = drawio.add_diagram(Path::new("."), &Config::default(), &mut chapter).unwrap();
+        // a figure per page captioned with its name, inside one for the link.
+        assert!(content.starts_with("<figure class=\"drawio-pages\" id=\"drawio-twopages\"><figure class=\"drawio\" id=\"drawio-twopages-one\">"));
+        assert!(content.contains("<figcaption><span class=\"drawio-caption\">One</span></figcaption></figure><figure class=\"drawio\" id=\"drawio-twopages-two\">"));
+        assert!(content.contains("<figcaption><span class=\"drawio-caption\">Two</span></figcaption></figure><figcaption>Request flow</figcaption></figure>\n"));
+        // the page shown again gets an id of its own.
+        assert!(content.contains("<figure class=\"drawio\" id=\"drawio-twopages-two-2\">"));
+
+        // built again, every page still comes from the cache.
+        let mut chapter = Chapter::new("Pages", markdown.to_string(), "../resources/pages.md", vec![]);
+        assert_eq!(drawio.add_diagram(Path::new("."), &Config::default(), &mut chapter).unwrap(), content);
+        let stats = drawio.stats.borrow();
+        let diagram_stats = stats.diagrams.values().next().unwrap();
+        assert_eq!((diagram_stats.cache_hits, diagram_stats.exports), (6, 0));
+    }
+
+    #[test]
+    fn edit_link_test() {
+        let diagram_path = Path::new("resources/twopages.drawio");
+        let mut config = Config {
+            edit_link: EditLink::Drawio,
+            ..Config::default()
+        };
+        // without a repository only the page shown goes along.
+        let link = edit_link(&config, diagram_path, diagram_path, false, Some("Two")).unwrap();
+        let url = svg::parse_attrs(&link).into_iter().find(|(k, _)| k == "href").unwrap().1;
+        let payload = url.strip_prefix("https://app.diagrams.net/#R").unwrap();
+        let xml = mxfile::decode(&percent_encoding::percent_decode_str(payload).decode_utf8().unwrap()).unwrap();
+        let pages = mxfile::pages(&xml);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].name, "Two");
+
+        config.source = Source::Strip;
+        assert_eq!(edit_link(&config, diagram_path, diagram_path, false, None), None);
+    }
 }
@@ -0,0 +1,557 @@
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::config::{Align, Config, Theme};
+use crate::mxfile::{Highlight, Rect};
+
+// links to diagrams in a chapter, either
+//   ![alt](diagram-Page.drawio)
+// or, to pass options along,
+//   ![alt](diagram.drawio#page=Page&layers=base,security)
+// or as a directive,
+//   {{#drawio diagram.drawio page="Page" layers="base,security"}}
+
+/// per link options.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DiagramOptions {
+    pub page: Option<String>,
+    /// every page, each in a figure of its own ...
+    pub all_pages: bool,
+    /// ... shown as tabs.
+    pub tabs: Option<bool>,
+    /// names (or ids) of the layers to show, all layers when None.
+    pub layers: Option<Vec<String>>,
+    /// crop to these cells (by id or label) ...
+    pub cells: Option<Vec<String>>,
+    /// ... or to this area of the page.
+    pub region: Option<Rect>,
+    /// space kept around the cropped cells or region.
+    pub margin: Option<f64>,
+    /// cells (by id or label) to draw attention to.
+    pub highlight: Option<Vec<String>>,
+    pub highlight_color: Option<String>,
+    pub highlight_width: Option<f64>,
+    /// fade out everything that isn't highlighted.
+    pub dim: bool,
+    pub dim_opacity: Option<u32>,
+    /// anchor the figure can be referenced by, `{{#fig id}}`.
+    pub id: Option<String>,
+    pub caption: Option<String>,
+    /// what the page is exported as.
+    pub format: Format,
+    /// size on the page in px, the other side follows when only one is given.
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    /// css length the diagram may not grow beyond, "600px" or "80%".
+    pub max_width: Option<String>,
+    /// factor applied to the exported size, when no width or height is given.
+    pub scale: Option<f64>,
+    pub align: Option<Align>,
+    /// overrides the book's `theme` for this diagram.
+    pub theme: Option<Theme>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Svg,
+    /// embedded as a data uri.
+    Png,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+        }
+    }
+}
+
+impl DiagramOptions {
+    /// parses `page=Overview&layers=a,b`, values may be percent encoded.
+    pub fn parse(fragment: &str) -> DiagramOptions {
+        let mut options = DiagramOptions::default();
+        for pair in fragment.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode_str(value).decode_utf8_lossy();
+            if let Err(f) = options.set(key, &value) {
+                log::warn!("{}", f);
+            }
+        }
+        options
+    }
+
+    /// sets one option, by the name used in links.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = |value: &str| {
+            value
+                .trim_end_matches("px")
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid {}: {}", key, value))
+        };
+        let value = value.to_string();
+        match key {
+            "page" => self.page = Some(value),
+            "all" => self.all_pages = !matches!(value.as_str(), "false" | "0"),
+            // tabs are for every page.
+            "tabs" => {
+                let tabs = !matches!(value.as_str(), "false" | "0");
+                self.tabs = Some(tabs);
+                self.all_pages |= tabs;
+            }
+            "layers" => self.layers = Some(split_list(&value)),
+            "cells" => self.cells = Some(split_list(&value)),
+            "region" => match Rect::parse(&value) {
+                Some(r) => self.region = Some(r),
+                None => return Err(format!("Invalid region, expected x,y,width,height: {}", value)),
+            },
+            "margin" => self.margin = Some(number(&value)?),
+            "highlight" => self.highlight = Some(split_list(&value)),
+            "highlight-color" => self.highlight_color = Some(value),
+            "highlight-width" => self.highlight_width = Some(number(&value)?),
+            // a bare `dim` turns it on.
+            "dim" => self.dim = !matches!(value.as_str(), "false" | "0"),
+            "id" => self.id = Some(value),
+            "caption" => self.caption = Some(value),
+            "format" => match value.as_str() {
+                "svg" => self.format = Format::Svg,
+                "png" => self.format = Format::Png,
+                _ => return Err(format!("Invalid format, expected svg or png: {}", value)),
+            },
+            "width" => self.width = Some(number(&value)?),
+            "height" => self.height = Some(number(&value)?),
+            "max-width" => match css_length(&value) {
+                Some(l) => self.max_width = Some(l),
+                None => return Err(format!("Invalid max-width: {}", value)),
+            },
+            "scale" => match value.strip_suffix('%') {
+                Some(percent) => self.scale = Some(number(percent)? / 100.0),
+                None => self.scale = Some(number(&value)?),
+            },
+            "align" => match serde_json::from_value(serde_json::Value::String(value.clone())) {
+                Ok(align) => self.align = Some(align),
+                Err(_) => return Err(format!("Invalid align, expected left, center or right: {}", value)),
+            },
+            "theme" => match serde_json::from_value(serde_json::Value::String(value.clone())) {
+                Ok(theme) => self.theme = Some(theme),
+                Err(_) => return Err(format!("Invalid theme: {}", value)),
+            },
+            _ => return Err(format!("Unknown diagram option: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// fills in whatever the link left out from the book wide settings.
+    pub fn apply_defaults(&mut self, config: &Config) {
+        // a size given in the link replaces the whole default size.
+        if self.width.is_none() && self.height.is_none() && self.scale.is_none() {
+            self.width = config.width;
+            self.height = config.height;
+            self.scale = config.scale;
+        }
+        if self.max_width.is_none() {
+            self.max_width = config.max_width.as_deref().and_then(css_length);
+        }
+        self.align = self.align.or(config.align);
+        if self.highlight.is_some() {
+            self.highlight_color
+                .get_or_insert_with(|| config.highlight_color.clone());
+            self.highlight_width.get_or_insert(config.highlight_width);
+            if self.dim {
+                self.dim_opacity.get_or_insert(config.dim_opacity);
+            }
+        }
+    }
+
+    pub fn highlight_style(&self) -> Highlight {
+        Highlight {
+            color: self.highlight_color.clone().unwrap_or_default(),
+            width: self.highlight_width.unwrap_or(1.0),
+            dim: self.dim_opacity.filter(|_| self.dim),
+        }
+    }
+
+    /// width and height in px for a diagram exported at `natural` size,
+    /// None for sides left to the exported size.
+    pub fn size(&self, natural: Option<(f64, f64)>) -> (Option<f64>, Option<f64>) {
+        match (self.width, self.height, natural) {
+            (Some(w), Some(h), _) => (Some(w), Some(h)),
+            (Some(w), None, Some((nw, nh))) if nw > 0.0 => (Some(w), Some(w * nh / nw)),
+            (None, Some(h), Some((nw, nh))) if nh > 0.0 => (Some(h * nw / nh), Some(h)),
+            (None, None, Some((nw, nh))) => match self.scale {
+                Some(s) => (Some(nw * s), Some(nh * s)),
+                None => (None, None),
+            },
+            (w, h, _) => (w, h),
+        }
+    }
+
+    /// true when the options only size, place, name or caption the page, an
+    /// editable image can be shown without exporting it. the theme is applied
+    /// to the svg afterwards either way.
+    pub fn only_placement(&self) -> bool {
+        let unplaced = DiagramOptions {
+            width: None,
+            height: None,
+            max_width: None,
+            scale: None,
+            align: None,
+            tabs: None,
+            id: None,
+            caption: None,
+            theme: None,
+            ..self.clone()
+        };
+        unplaced == DiagramOptions::default()
+    }
+
+    /// true when any of the size options is set.
+    pub fn sized(&self) -> bool {
+        self.width.is_some() || self.height.is_some() || self.scale.is_some() || self.max_width.is_some()
+    }
+
+    /// true when only part of the page is shown.
+    pub fn crops(&self) -> bool {
+        self.cells.is_some() || self.region.is_some()
+    }
+}
+
+// "600" -> "600px", other css lengths are kept.
+fn css_length(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.parse::<f64>().is_ok() {
+        return Some(format!("{}px", value));
+    }
+    let number = ["px", "%", "em", "rem", "vw"]
+        .iter()
+        .find_map(|unit| value.strip_suffix(unit))?;
+    number.parse::<f64>().ok().map(|_| value.to_string())
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagramLink {
+    /// where the link is in the chapter.
+    pub range: Range<usize>,
+    /// the text between the square brackets.
+    pub alt: String,
+    /// the quoted title after the path, if any.
+    pub title: Option<String>,
+    /// path to the diagram file, relative to the chapter.
+    pub path: String,
+    pub options: DiagramOptions,
+    /// written out in the chapter as a code block, `path` is where it
+    /// was saved to, relative to the book.
+    pub generated: bool,
+}
+
+/// links to files with one of the `extensions` (without the dot) or to
+/// editable images, in a chapter in `dir`.
+pub fn find_links<P: AsRef<Path>>(content: &str, extensions: &[String], dir: P) -> Vec<DiagramLink> {
+    let extensions: String = extensions
+        .iter()
+        .map(|e| format!(r"|\.{}", regex::escape(e.trim_start_matches('.'))))
+        .collect();
+    let regex_v = Regex::new(&format!(
+        r#"!\[([^\]]*)\]\(([^)\s#]*(?:\.drawio\.svg|\.drawio\.png{}))(?:#([^)\s]*))?(?:\s+"([^"]*)")?\s*\)"#,
+        extensions
+    ))
+    .unwrap();
+
+    regex_v
+        .captures_iter(content)
+        .map(|caps| {
+            let path = caps[2].to_string();
+            let (path, options) = match caps.get(3) {
+                Some(fragment) => (path, DiagramOptions::parse(fragment.as_str())),
+                None if is_image(&path) => (path, DiagramOptions::default()),
+                None => split_legacy(&path, dir.as_ref()),
+            };
+            DiagramLink {
+                range: caps.get(0).unwrap().range(),
+                alt: caps[1].to_string(),
+                title: caps.get(4).map(|t| t.as_str().to_string()),
+                path,
+                options,
+                generated: false,
+            }
+        })
+        .collect()
+}
+
+/// editable images, .drawio.svg and .drawio.png, have the diagram embedded.
+pub fn is_image<P: AsRef<Path>>(path: P) -> bool {
+    let name = path.as_ref().to_string_lossy().to_lowercase();
+    name.ends_with(".drawio.svg") || name.ends_with(".drawio.png")
+}
+
+/// the file name without the extension(s), "arch" for "arch.drawio.svg".
+pub fn diagram_name<P: AsRef<Path>>(path: P) -> String {
+    let path = path.as_ref();
+    let stem = path.file_stem().unwrap_or_default();
+    let stem = match is_image(path) {
+        true => Path::new(stem).file_stem().unwrap_or_default(),
+        false => stem,
+    };
+    stem.to_string_lossy().into_owned()
+}
+
+/// a `{{#drawio}}` that couldn't be read, `line` is 1 based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectiveError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// `{{#drawio path key="value" key=value}}` directives, like mdbook's own
+/// `{{#include}}`. takes the same options as links. those in code are left
+/// alone so a book can show them.
+pub fn find_directives(content: &str) -> Vec<Result<DiagramLink, DirectiveError>> {
+    let regex_v = Regex::new(r#"\{\{#drawio\b((?:[^}"]|"(?:[^"\\]|\\.)*")*)\}\}"#).unwrap();
+    let code = crate::block::code_ranges(content);
+
+    regex_v
+        .captures_iter(content)
+        .filter(|caps| !code.iter().any(|r| r.contains(&caps.get(0).unwrap().start())))
+        .map(|caps| {
+            let range = caps.get(0).unwrap().range();
+            let error = |message: String| DirectiveError {
+                line: content[..range.start].matches('\n').count() + 1,
+                message,
+            };
+
+            let mut arguments = split_arguments(&caps[1]).map_err(error)?.into_iter();
+            let path = match arguments.next() {
+                Some((path, None)) => path,
+                _ => return Err(error("expected the path of a diagram".to_string())),
+            };
+            let mut options = DiagramOptions::default();
+            for (key, value) in arguments {
+                options
+                    .set(&key, value.as_deref().unwrap_or(""))
+                    .map_err(error)?;
+            }
+            Ok(DiagramLink {
+                range,
+                alt: String::new(),
+                title: None,
+                path,
+                options,
+                generated: false,
+            })
+        })
+        .collect()
+}
+
+// `a key="quoted \" value" flag` into (a, None), (key, Some(quoted " value))
+// and (flag, None).
+fn split_arguments(text: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut arguments = vec![];
+    let mut chars = text.chars().peekable();
+    // a bare or quoted word.
+    let mut word = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Result<String, String> {
+        let mut word = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(word),
+                    Some('\\') => word.extend(chars.next()),
+                    Some(c) => word.push(c),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+        }
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            word.push(c);
+        }
+        Ok(word)
+    };
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(arguments);
+        }
+        let key = word(&mut chars)?;
+        if key.is_empty() {
+            return Err(format!("unexpected {:?}", chars.peek().unwrap()));
+        }
+        let value = match chars.next_if_eq(&'=') {
+            Some(_) => Some(word(&mut chars)?),
+            None => None,
+        };
+        arguments.push((key, value));
+    }
+}
+
+// "dir/diagram-Page-1.drawio" is the page "Page-1" of "dir/diagram.drawio",
+// if there is such a file in `dir`. "my-arch.drawio" may be the whole name.
+fn split_legacy(path: &str, dir: &Path) -> (String, DiagramOptions) {
+    let file_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let (stem, extension) = match path[file_start..].rfind('.') {
+        Some(i) => path.split_at(file_start + i),
+        None => (path, ""),
+    };
+    match stem[file_start..].find('-') {
+        Some(i) if dir.join(format!("{}{}", &stem[..file_start + i], extension)).is_file() => {
+            let split = file_start + i;
+            let options = DiagramOptions {
+                page: Some(stem[split + 1..].to_string()),
+                ..Default::default()
+            };
+            (format!("{}{}", &stem[..split], extension), options)
+        }
+        _ => (path.to_string(), DiagramOptions::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_directives_test() {
+        let content = r#"# Design
+{{#drawio arch/overview.drawio page="Net zone" format=png width=600 caption="Say \"hi\"" dim}}
+
+{{#drawio page="Net"}}
+{{#drawio arch.drawio width=wide}}
+
+Written `{{#drawio arch.drawio}}`, or
+
+```md
+{{#drawio arch.drawio page="Net"}}
+```
+"#;
+        let directives = find_directives(content);
+        assert_eq!(directives.len(), 3);
+
+        let link = directives[0].as_ref().unwrap();
+        assert_eq!(link.path, "arch/overview.drawio");
+        assert_eq!(link.options.page.as_deref(), Some("Net zone"));
+        assert_eq!(link.options.format, Format::Png);
+        assert_eq!(link.options.width, Some(600.0));
+        assert_eq!(link.options.caption.as_deref(), Some("Say \"hi\""));
+        assert!(link.options.dim);
+        assert!(content[link.range.clone()].ends_with("dim}}"));
+
+        let error = directives[1].as_ref().unwrap_err();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.message, "expected the path of a diagram");
+        assert_eq!(directives[2].as_ref().unwrap_err().message, "Invalid width: wide");
+    }
+
+    #[test]
+    fn find_links_test() {
+        let content = r#"
+![legacy](./dir-a/diagram-Page-1.drawio)
+![options](arch.drawio#page=Net%20zone&layers=base,%20security)
+![image](photo.png)
+![editable](img/flow-chart.drawio.svg)
+![vscode](net-Overview.dio)
+![export](net.xml#page=Overview)
+![titled](net.drawio#page=Overview&id=net "Network overview")
+![hyphen](my-arch.drawio)
+"#;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir(dir.join("dir-a")).unwrap();
+        for file in ["dir-a/diagram.drawio", "net.dio", "my-arch.drawio"] {
+            std::fs::write(dir.join(file), "<mxfile/>").unwrap();
+        }
+        let extensions = Config::default().extensions;
+        let links = find_links(content, &extensions, dir);
+        assert_eq!(links.len(), 6);
+        // there is no my.drawio, the name has a hyphen.
+        assert_eq!(links[5].path, "my-arch.drawio");
+        assert_eq!(links[5].options.page, None);
+        assert_eq!(links[4].title.as_deref(), Some("Network overview"));
+        assert_eq!(links[4].options.id.as_deref(), Some("net"));
+        assert_eq!(links[3].path, "net.dio");
+        assert_eq!(links[3].options.page.as_deref(), Some("Overview"));
+        assert_eq!(find_links(content, &["xml".to_string()], dir).len(), 2);
+        assert_eq!(links[2].path, "img/flow-chart.drawio.svg");
+        assert_eq!(links[2].options, DiagramOptions::default());
+        assert_eq!(diagram_name(&links[2].path), "flow-chart");
+
+        assert_eq!(links[0].alt, "legacy");
+        assert_eq!(links[0].path, "./dir-a/diagram.drawio");
+        assert_eq!(links[0].options.page.as_deref(), Some("Page-1"));
+        assert_eq!(
+            &content[links[0].range.clone()],
+            "![legacy](./dir-a/diagram-Page-1.drawio)"
+        );
+
+        assert_eq!(links[1].path, "arch.drawio");
+        assert_eq!(links[1].options.page.as_deref(), Some("Net zone"));
+        assert_eq!(
+            links[1].options.layers,
+            Some(vec!["base".to_string(), "security".to_string()])
+        );
+
+        let options = DiagramOptions::parse("page=A&cells=db,cache&region=1,2,3&margin=5");
+        assert_eq!(
+            options.cells,
+            Some(vec!["db".to_string(), "cache".to_string()])
+        );
+        // the region is missing its height.
+        assert_eq!(options.region, None);
+        assert_eq!(options.margin, Some(5.0));
+        assert!(options.crops());
+
+        let mut options = DiagramOptions::parse("page=A&highlight=auth&dim");
+        options.apply_defaults(&Config::default());
+        assert_eq!(
+            options.highlight_style(),
+            Highlight {
+                color: "#ff0000".to_string(),
+                width: 3.0,
+                dim: Some(25)
+            }
+        );
+        let mut other = DiagramOptions::parse("page=A&highlight=auth");
+        other.apply_defaults(&Config::default());
+        assert_eq!(other.highlight_style().dim, None);
+    }
+
+    #[test]
+    fn size_test() {
+        let config = Config {
+            width: Some(800.0),
+            max_width: Some("90%".to_string()),
+            align: Some(Align::Center),
+            ..Config::default()
+        };
+        let mut options = DiagramOptions::parse("height=100px&max-width=600&align=right");
+        options.apply_defaults(&config);
+        assert_eq!(options.width, None);
+        assert_eq!(options.max_width.as_deref(), Some("600px"));
+        assert_eq!(options.align, Some(Align::Right));
+        assert_eq!(options.size(Some((400.0, 200.0))), (Some(200.0), Some(100.0)));
+        assert!(options.only_placement());
+
+        let mut options = DiagramOptions::parse("scale=0.5");
+        options.apply_defaults(&config);
+        assert_eq!(options.size(Some((400.0, 200.0))), (Some(200.0), Some(100.0)));
+        assert_eq!(options.max_width.as_deref(), Some("90%"));
+        assert_eq!(DiagramOptions::parse("align=top").align, None);
+
+        // tabs are for every page, without them nothing needs exporting.
+        assert!(DiagramOptions::parse("tabs").all_pages);
+        assert!(DiagramOptions::parse("tabs=false").only_placement());
+        // nor do figure ids, captions and themes.
+        assert!(DiagramOptions::parse("id=flow&caption=Flow&theme=light-dark").only_placement());
+        assert!(!DiagramOptions::parse("id=flow&page=Two").only_placement());
+    }
+}
@@ -0,0 +1,711 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::svg;
+
+// reading the mxfile xml draw.io saves diagrams as.
+// <mxfile><diagram id=".." name="Page-1">...</diagram>...</mxfile>
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub id: String,
+    pub name: String,
+    // whatever is inside the <diagram> element, left as is.
+    pub content: String,
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#10;", "\n")
+        .replace("&#xa;", "\n")
+        .replace("&amp;", "&")
+}
+
+/// pages of the diagram in document order.
+pub fn pages(xml: &str) -> Vec<Page> {
+    let diagram_regex = Regex::new(r"(?s)<diagram\b([^>]*?)(?:/>|>(.*?)</diagram>)").unwrap();
+    diagram_regex
+        .captures_iter(xml)
+        .map(|caps| {
+            let attrs = svg::parse_attrs(&caps[1]);
+            let attr = |name| {
+                svg::get_attr(&attrs, name)
+                    .map(unescape)
+                    .unwrap_or_default()
+            };
+            Page {
+                id: attr("id"),
+                name: attr("name"),
+                content: caps.get(2).map(|c| c.as_str()).unwrap_or("").to_string(),
+            }
+        })
+        .collect()
+}
+
+pub fn read_pages<P: AsRef<Path>>(path: P) -> Result<Vec<Page>, String> {
+    Ok(pages(&read(path)?))
+}
+
+/// the mxfile xml of a diagram file. editable images (.drawio.svg and
+/// .drawio.png) carry it in their metadata.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<String, String> {
+    let path = path.as_ref();
+    let name = path.to_string_lossy().to_lowercase();
+    if name.ends_with(".svg") {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let root = Regex::new(r"<svg\b([^>]*)>").unwrap();
+        let attrs = svg::parse_attrs(root.captures(&text).ok_or("not an svg")?.get(1).unwrap().as_str());
+        Ok(unescape(svg::get_attr(&attrs, "content").ok_or("no diagram in the svg")?))
+    } else if name.ends_with(".png") {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let text = png_text(&bytes, "mxfile").ok_or("no diagram in the png")?;
+        Ok(percent_decode_str(&text).decode_utf8_lossy().into_owned())
+    } else {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        // some exports are a single page without the <mxfile> around it.
+        match svg::skip_prolog(&text) {
+            model if model.starts_with("<mxGraphModel") => from_source(model),
+            _ => Ok(text),
+        }
+    }
+}
+
+// the value of a tEXt (or zTXt) chunk of a png.
+fn png_text(png: &[u8], keyword: &str) -> Option<String> {
+    let mut rest = png.strip_prefix(b"\x89PNG\r\n\x1a\n")?;
+    // length, type, data and crc.
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        let kind = &rest[4..8];
+        let data = rest.get(8..8 + len)?;
+        rest = rest.get(12 + len..)?;
+
+        let split = match data.iter().position(|b| *b == 0) {
+            Some(i) if &data[..i] == keyword.as_bytes() => i,
+            _ => continue,
+        };
+        match kind {
+            // latin-1, which is ascii for url encoded text.
+            b"tEXt" => return Some(data[split + 1..].iter().map(|b| *b as char).collect()),
+            b"zTXt" => {
+                let mut text = String::new();
+                flate2::read::ZlibDecoder::new(data.get(split + 2..)?)
+                    .read_to_string(&mut text)
+                    .ok()?;
+                return Some(text);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// the mxGraphModel xml of a page. draw.io stores pages either as plain xml
+/// or compressed: url encoded, deflated and base64 encoded.
+pub fn decode(content: &str) -> Result<String, String> {
+    let content = content.trim();
+    if content.is_empty() || content.starts_with('<') {
+        return Ok(content.to_string());
+    }
+
+    let deflated = base64::decode(content).map_err(|e| e.to_string())?;
+    let mut inflated = String::new();
+    DeflateDecoder::new(&deflated[..])
+        .read_to_string(&mut inflated)
+        .map_err(|e| e.to_string())?;
+    percent_decode_str(&inflated)
+        .decode_utf8()
+        .map(|s| s.into_owned())
+        .map_err(|e| e.to_string())
+}
+
+// what javascript's encodeURIComponent leaves alone, draw.io encodes with it.
+pub const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// compresses xml the way draw.io does, the reverse of `decode`.
+pub fn compress(xml: &str) -> String {
+    let encoded = utf8_percent_encode(xml, URI_COMPONENT).to_string();
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    // writing to a vec can't fail.
+    encoder.write_all(encoded.as_bytes()).unwrap();
+    base64::encode(encoder.finish().unwrap())
+}
+
+/// an mxfile holding the given pages, their content is written as is.
+pub fn write(pages: &[Page]) -> String {
+    let mut xml = String::from("<mxfile>");
+    for page in pages {
+        xml += &format!(
+            "<diagram id=\"{}\" name=\"{}\">{}</diagram>",
+            escape(&page.id),
+            escape(&page.name),
+            page.content
+        );
+    }
+    xml += "</mxfile>";
+    xml
+}
+
+/// an mxfile from diagram source pasted into a chapter: a whole mxfile,
+/// a single <mxGraphModel>, or a compressed page.
+pub fn from_source(source: &str) -> Result<String, String> {
+    let source = source.trim();
+    if source.starts_with("<mxfile") {
+        return Ok(source.to_string());
+    }
+
+    let content = if source.starts_with("<mxGraphModel") {
+        source.to_string()
+    } else {
+        // pasted payloads tend to be wrapped over several lines.
+        let compressed: String = source.split_whitespace().collect();
+        if !decode(&compressed).is_ok_and(|m| m.starts_with("<mxGraphModel")) {
+            return Err("expected an <mxfile>, an <mxGraphModel> or a compressed diagram".to_string());
+        }
+        compressed
+    };
+    Ok(write(&[Page {
+        id: "page-1".to_string(),
+        name: "Page-1".to_string(),
+        content,
+    }]))
+}
+
+// calls `f` with the id, label and attributes of every <mxCell>. cells with
+// properties are wrapped in an <object> that carries the id and label.
+fn rewrite_cells<F>(model: &str, mut f: F) -> String
+where
+    F: FnMut(&str, &str, &mut Vec<(String, String)>),
+{
+    let mut object: Vec<(String, String)> = vec![];
+
+    svg::rewrite_tags(model, |name, attrs| {
+        if name == "object" || name == "UserObject" {
+            object = attrs.clone();
+            return;
+        }
+        if name != "mxCell" {
+            return;
+        }
+
+        let attr = |name, object_name| {
+            svg::get_attr(attrs, name)
+                .or_else(|| svg::get_attr(&object, object_name))
+                .map(unescape)
+                .unwrap_or_default()
+        };
+        let id = attr("id", "id");
+        let label = attr("value", "label");
+        object.clear();
+        f(&id, &label, attrs);
+    })
+}
+
+/// hides every layer not listed in `layers`. layers are the cells whose
+/// parent is the root cell "0", matched by name or id. the unnamed default
+/// layer is called "Background" like in draw.io.
+pub fn show_layers(model: &str, layers: &[String]) -> String {
+    let mut layer_count = 0;
+
+    rewrite_cells(model, |id, label, attrs| {
+        if svg::get_attr(attrs, "parent") != Some("0") {
+            return;
+        }
+        let label = if label.is_empty() && layer_count == 0 {
+            "Background"
+        } else {
+            label
+        };
+        layer_count += 1;
+
+        if !layers.iter().any(|l| l == id || l == label) {
+            svg::set_attr(attrs, "visible", "0");
+        }
+    })
+}
+
+/// sets `key=value` in a draw.io style string, replacing an existing value.
+pub fn set_style(style: &str, key: &str, value: &str) -> String {
+    let mut parts: Vec<&str> = style
+        .split(';')
+        .filter(|p| !p.is_empty() && p.split('=').next() != Some(key))
+        .collect();
+    let entry = format!("{}={}", key, value);
+    parts.push(&entry);
+    parts.join(";") + ";"
+}
+
+/// how highlighted cells are drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    pub color: String,
+    pub width: f64,
+    /// opacity (0-100) for every other shape and edge, None to leave them.
+    pub dim: Option<u32>,
+}
+
+/// restyles the cells (by id or label) in `wanted`.
+pub fn highlight(model: &str, wanted: &[String], style: &Highlight) -> String {
+    rewrite_cells(model, |id, label, attrs| {
+        let drawn = svg::get_attr(attrs, "vertex") == Some("1")
+            || svg::get_attr(attrs, "edge") == Some("1");
+        if !drawn {
+            return;
+        }
+
+        let mut cell_style = svg::get_attr(attrs, "style").unwrap_or("").to_string();
+        if wanted.iter().any(|w| w == id || w == label) {
+            cell_style = set_style(&cell_style, "strokeColor", &style.color);
+            cell_style = set_style(&cell_style, "strokeWidth", &style.width.to_string());
+        } else if let Some(opacity) = style.dim {
+            cell_style = set_style(&cell_style, "opacity", &opacity.to_string());
+            cell_style = set_style(&cell_style, "textOpacity", &opacity.to_string());
+        } else {
+            return;
+        }
+        svg::set_attr(attrs, "style", &cell_style);
+    })
+}
+
+/// fills in placeholders with the values from `resolve`. `{{name}}` is
+/// replaced in labels, tooltips and links. `%name%` is left to draw.io, which
+/// resolves it for cells with placeholders="1" by looking at the properties
+/// of the cell and its ancestors, so the values are added to the root cell.
+pub fn substitute<F>(model: &str, resolve: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let braces = Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").unwrap();
+    let mut model = svg::rewrite_tags(model, |_, attrs| {
+        for (key, value) in attrs.iter_mut() {
+            if !matches!(key.as_str(), "value" | "label" | "tooltip" | "link") {
+                continue;
+            }
+            *value = braces
+                .replace_all(value, |caps: &regex::Captures| match resolve(&caps[1]) {
+                    Some(v) => escape(&v),
+                    None => caps[0].to_string(),
+                })
+                .to_string();
+        }
+    });
+
+    if !model.contains("placeholders=\"1\"") {
+        return model;
+    }
+    let percent = Regex::new(r"%([A-Za-z_][\w.-]*)%").unwrap();
+    let mut values: Vec<(String, String)> = vec![];
+    for caps in percent.captures_iter(&model) {
+        let name = caps[1].to_string();
+        if values.iter().any(|(n, _)| *n == name) {
+            continue;
+        }
+        if let Some(value) = resolve(&name) {
+            values.push((name, escape(&value)));
+        }
+    }
+    if values.is_empty() {
+        return model;
+    }
+
+    // the root cell only becomes an <object> once it has properties.
+    let object_root = Regex::new(r#"<object\b[^>]*\sid="0""#).unwrap();
+    let plain_root = Regex::new(r#"<mxCell\s+id="0"\s*/>"#).unwrap();
+    if !object_root.is_match(&model) {
+        model = plain_root
+            .replace(&model, r#"<object label="" id="0"><mxCell/></object>"#)
+            .to_string();
+    }
+    let mut root = false;
+    svg::rewrite_tags(&model, |name, attrs| {
+        if name != "object" || svg::get_attr(attrs, "id") != Some("0") || root {
+            return;
+        }
+        root = true;
+        for (name, value) in values.iter() {
+            // values set on the diagram itself win.
+            if svg::get_attr(attrs, name).is_none() {
+                svg::set_attr(attrs, name, value);
+            }
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    /// parses "x,y,width,height".
+    pub fn parse(text: &str) -> Option<Rect> {
+        let v: Vec<f64> = text
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        match v[..] {
+            [x, y, width, height] => Some(Rect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    pub fn grow(&self, margin: f64) -> Rect {
+        Rect {
+            x: self.x - margin,
+            y: self.y - margin,
+            width: self.width + 2.0 * margin,
+            height: self.height + 2.0 * margin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cell {
+    pub id: String,
+    pub label: String,
+    pub parent: String,
+    pub vertex: bool,
+    /// `visible="0"`, set on layers hidden by `show_layers`.
+    pub hidden: bool,
+    /// position relative to the parent.
+    pub geometry: Option<Rect>,
+    /// waypoints and loose ends of an edge, relative to the parent.
+    pub points: Vec<(f64, f64)>,
+}
+
+/// the cells of an mxGraphModel, cells with properties are wrapped in an
+/// <object> carrying the id and label.
+pub fn cells(model: &str) -> Vec<Cell> {
+    let cell_regex = Regex::new(
+        r"(?s)(?:<(?:object|UserObject)\b([^>]*)>\s*)?<mxCell\b([^>]*?)(?:/>|>(.*?)</mxCell>)",
+    )
+    .unwrap();
+    let geometry_regex = Regex::new(r"<mxGeometry\b([^>]*?)/?>").unwrap();
+    let point_regex = Regex::new(r"<mxPoint\b([^>]*?)/?>").unwrap();
+    let number = |attrs: &[(String, String)], name| {
+        svg::get_attr(attrs, name)
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    };
+
+    cell_regex
+        .captures_iter(model)
+        .map(|caps| {
+            let object = caps
+                .get(1)
+                .map(|o| svg::parse_attrs(o.as_str()))
+                .unwrap_or_default();
+            let attrs = svg::parse_attrs(&caps[2]);
+            let attr = |name, object_name| {
+                svg::get_attr(&attrs, name)
+                    .or_else(|| svg::get_attr(&object, object_name))
+                    .map(unescape)
+                    .unwrap_or_default()
+            };
+            let body = caps.get(3).map(|b| b.as_str()).unwrap_or("");
+
+            let geometry = geometry_regex
+                .captures(body)
+                .map(|g| svg::parse_attrs(&g[1]));
+            let vertex = svg::get_attr(&attrs, "vertex") == Some("1");
+            Cell {
+                id: attr("id", "id"),
+                label: attr("value", "label"),
+                parent: attr("parent", "parent"),
+                vertex,
+                hidden: svg::get_attr(&attrs, "visible") == Some("0"),
+                geometry: geometry.as_ref().filter(|_| vertex).map(|g| Rect {
+                    x: number(g, "x"),
+                    y: number(g, "y"),
+                    width: number(g, "width"),
+                    height: number(g, "height"),
+                }),
+                points: point_regex
+                    .captures_iter(body)
+                    .map(|p| {
+                        let p = svg::parse_attrs(&p[1]);
+                        (number(&p, "x"), number(&p, "y"))
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// the ids of the cells that are drawn, neither hidden themselves nor inside
+/// a hidden layer, group or container.
+pub fn shown(cells: &[Cell]) -> HashSet<String> {
+    let by_id: HashMap<&str, &Cell> = cells.iter().map(|c| (c.id.as_str(), c)).collect();
+    cells
+        .iter()
+        .filter(|cell| {
+            let mut current = Some(*cell);
+            // guard against cycles in broken files.
+            for _ in 0..cells.len() {
+                match current {
+                    Some(c) if c.hidden => return false,
+                    Some(c) => current = by_id.get(c.parent.as_str()).copied(),
+                    None => break,
+                }
+            }
+            true
+        })
+        .map(|c| c.id.clone())
+        .collect()
+}
+
+/// bounds of each cell in page coordinates, children of groups and
+/// containers are positioned relative to their parent.
+pub fn absolute_bounds(cells: &[Cell]) -> HashMap<String, Rect> {
+    let by_id: HashMap<&str, &Cell> = cells.iter().map(|c| (c.id.as_str(), c)).collect();
+    let origin = |cell: &Cell| {
+        let (mut x, mut y) = (0.0, 0.0);
+        let mut parent = by_id.get(cell.parent.as_str());
+        // guard against cycles in broken files.
+        for _ in 0..cells.len() {
+            match parent.and_then(|p| p.geometry.map(|g| (p, g))) {
+                Some((p, g)) => {
+                    x += g.x;
+                    y += g.y;
+                    parent = by_id.get(p.parent.as_str());
+                }
+                None => break,
+            }
+        }
+        (x, y)
+    };
+
+    let mut bounds = HashMap::new();
+    for cell in cells {
+        let (ox, oy) = origin(cell);
+        let mut rect = cell.geometry.map(|g| Rect {
+            x: g.x + ox,
+            y: g.y + oy,
+            ..g
+        });
+        for (px, py) in cell.points.iter() {
+            let point = Rect {
+                x: px + ox,
+                y: py + oy,
+                width: 0.0,
+                height: 0.0,
+            };
+            rect = Some(rect.map_or(point, |r| r.union(&point)));
+        }
+        if let Some(rect) = rect {
+            bounds.insert(cell.id.clone(), rect);
+        }
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn pages_test() {
+        let xml = r#"<mxfile><diagram id="a1" name="Current &amp; target">abc</diagram><diagram name="Empty" id="b2"/></mxfile>"#;
+        assert_eq!(
+            pages(xml),
+            vec![
+                Page {
+                    id: "a1".into(),
+                    name: "Current & target".into(),
+                    content: "abc".into()
+                },
+                Page {
+                    id: "b2".into(),
+                    name: "Empty".into(),
+                    content: "".into()
+                },
+            ]
+        );
+
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let pages = read_pages(resources_dir.join("testdiagram.drawio")).unwrap();
+        assert_eq!(pages[0].name, "Page-1");
+    }
+
+    #[test]
+    fn read_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let xml = r#"<mxfile><diagram id="a" name="P">x &amp; y</diagram></mxfile>"#;
+
+        let svg_path = temp_dir.path().join("a.drawio.svg");
+        std::fs::write(
+            &svg_path,
+            format!("<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" content=\"{}\"><g/></svg>", escape(xml)),
+        )
+        .unwrap();
+        assert_eq!(read(&svg_path).unwrap(), xml);
+
+        // signature, a chunk in the way, then the diagram url encoded.
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [
+            (&b"IHDR"[..], b"0123456789abc".to_vec()),
+            (b"tEXt", [&b"mxfile\0"[..], percent_encoding::utf8_percent_encode(xml, percent_encoding::NON_ALPHANUMERIC).to_string().as_bytes()].concat()),
+        ] {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(&data);
+            png.extend([0; 4]);
+        }
+        let png_path = temp_dir.path().join("a.drawio.png");
+        std::fs::write(&png_path, png).unwrap();
+        assert_eq!(read_pages(&png_path).unwrap()[0].content, "x &amp; y");
+
+        std::fs::write(&svg_path, "<svg/>").unwrap();
+        assert!(read(&svg_path).is_err());
+
+        // a bare page, as some .xml exports are.
+        let xml_path = temp_dir.path().join("a.xml");
+        std::fs::write(&xml_path, "<?xml version=\"1.0\"?>\n<mxGraphModel><root/></mxGraphModel>").unwrap();
+        assert_eq!(read_pages(&xml_path).unwrap()[0].content, "<mxGraphModel><root/></mxGraphModel>");
+    }
+
+    #[test]
+    fn decode_test() {
+        let resources_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let pages = read_pages(resources_dir.join("testdiagram.drawio")).unwrap();
+        let model = decode(&pages[0].content).unwrap();
+        assert!(model.starts_with("<mxGraphModel"));
+
+        // plain pages are left alone.
+        assert_eq!(decode(&model).unwrap(), model);
+        assert_eq!(decode(&compress(&model)).unwrap(), model);
+        let written = write(&[Page {
+            content: model.clone(),
+            ..pages[0].clone()
+        }]);
+        assert_eq!(decode(&self::pages(&written)[0].content).unwrap(), model);
+
+        // pasted into a chapter, wrapped over lines or not.
+        let compressed = pages[0].content.trim();
+        let wrapped = format!("{}\n{}", &compressed[..10], &compressed[10..]);
+        let file = from_source(&wrapped).unwrap();
+        assert_eq!(decode(&self::pages(&file)[0].content).unwrap(), model);
+        let file = from_source(&model).unwrap();
+        assert_eq!(self::pages(&file)[0].name, "Page-1");
+        assert!(from_source("not a diagram").is_err());
+    }
+
+    #[test]
+    fn bounds_test() {
+        let model = r#"<mxGraphModel><root><mxCell id="0"/><mxCell id="1" parent="0"/>
+<mxCell id="group" parent="1" vertex="1"><mxGeometry x="100" y="50" width="200" height="100" as="geometry"/></mxCell>
+<object label="Database" id="db"><mxCell parent="group" vertex="1"><mxGeometry x="10" y="20" width="80" height="40" as="geometry"/></mxCell></object>
+<mxCell id="e1" edge="1" parent="1"><mxGeometry relative="1" as="geometry"><mxPoint x="400" y="10" as="targetPoint"/></mxGeometry></mxCell>
+</root></mxGraphModel>"#;
+        let cells = cells(model);
+        assert_eq!(cells.len(), 5);
+        assert_eq!(cells[3].label, "Database");
+
+        let bounds = absolute_bounds(&cells);
+        assert_eq!(bounds["db"], Rect::parse("110,70,80,40").unwrap());
+        assert_eq!(bounds["e1"], Rect::parse("400,10,0,0").unwrap());
+        assert!(!bounds.contains_key("1"));
+        assert_eq!(
+            bounds["db"].union(&bounds["e1"]).grow(10.0),
+            Rect::parse("100,0,310,120").unwrap()
+        );
+    }
+
+    #[test]
+    fn highlight_test() {
+        let model = r#"<root><mxCell id="1" parent="0"/><object label="auth" id="a1"><mxCell style="rounded=1;strokeColor=#000000;" parent="1" vertex="1"/></object><mxCell id="db" value="db" parent="1" vertex="1"/></root>"#;
+        let style = Highlight {
+            color: "#ff0000".to_string(),
+            width: 3.0,
+            dim: Some(30),
+        };
+        assert_eq!(
+            highlight(model, &["auth".to_string()], &style),
+            r#"<root><mxCell id="1" parent="0"/><object label="auth" id="a1"><mxCell style="rounded=1;strokeColor=#ff0000;strokeWidth=3;" parent="1" vertex="1"/></object><mxCell id="db" value="db" parent="1" vertex="1" style="opacity=30;textOpacity=30;"/></root>"#
+        );
+    }
+
+    #[test]
+    fn substitute_test() {
+        let resolve = |name: &str| match name {
+            "version" => Some("1.2".to_string()),
+            "product_name" => Some("A & B".to_string()),
+            _ => None,
+        };
+
+        let model = r#"<root><mxCell id="0"/><mxCell id="c1" value="{{product_name}} {{ missing }}" parent="1" vertex="1"/></root>"#;
+        assert_eq!(
+            substitute(model, resolve),
+            r#"<root><mxCell id="0"/><mxCell id="c1" value="A &amp; B {{ missing }}" parent="1" vertex="1"/></root>"#
+        );
+
+        let model = r#"<root><mxCell id="0"/><object label="v%version% %other%" placeholders="1" id="c1"><mxCell parent="1" vertex="1"/></object></root>"#;
+        assert_eq!(
+            substitute(model, resolve),
+            r#"<root><object label="" id="0" version="1.2"><mxCell/></object><object label="v%version% %other%" placeholders="1" id="c1"><mxCell parent="1" vertex="1"/></object></root>"#
+        );
+
+        // values already on the root are kept.
+        let model = r#"<root><object label="" version="0.1" id="0"><mxCell/></object><object label="%version%" placeholders="1" id="c1"><mxCell parent="1" vertex="1"/></object></root>"#;
+        assert_eq!(substitute(model, resolve), model);
+    }
+
+    #[test]
+    fn show_layers_test() {
+        let model = r#"<mxGraphModel><root><mxCell id="0"/><mxCell id="1" parent="0"/><mxCell id="l2" value="security" parent="0"/><object label="details" id="l3"><mxCell parent="0"/></object><mxCell id="c1" value="db" parent="1" vertex="1"/></root></mxGraphModel>"#;
+        let shown = show_layers(model, &["Background".to_string(), "details".to_string()]);
+        assert_eq!(
+            shown,
+            r#"<mxGraphModel><root><mxCell id="0"/><mxCell id="1" parent="0"/><mxCell id="l2" value="security" parent="0" visible="0"/><object label="details" id="l3"><mxCell parent="0"/></object><mxCell id="c1" value="db" parent="1" vertex="1"/></root></mxGraphModel>"#
+        );
+
+        // cells in a hidden layer aren't drawn.
+        let drawn = super::shown(&cells(&show_layers(model, &["details".to_string()])));
+        assert!(drawn.contains("l3"));
+        assert!(!drawn.contains("1") && !drawn.contains("c1"));
+    }
+}
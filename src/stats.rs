@@ -0,0 +1,110 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+// exporting is by far the slowest part of a build, these numbers
+// are collected while the book is processed so slow diagrams
+// can be spotted.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildStats {
+    // keyed by the diagram path, sorted so the report is stable.
+    pub diagrams: BTreeMap<String, DiagramStats>,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiagramStats {
+    // number of links in the book pointing at this diagram.
+    pub references: usize,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    // times the exporter was run for this diagram.
+    pub exports: usize,
+    pub failures: usize,
+    // time spent handling every reference to this diagram.
+    pub elapsed_ms: f64,
+}
+
+impl DiagramStats {
+    pub fn add_time(&mut self, elapsed: Duration) {
+        self.elapsed_ms += elapsed.as_secs_f64() * 1000.0;
+    }
+}
+
+impl BuildStats {
+    /// gets the entry for a diagram, creating it on first use.
+    pub fn diagram<P: AsRef<Path>>(&mut self, path: P) -> &mut DiagramStats {
+        self.diagrams
+            .entry(path.as_ref().to_string_lossy().into_owned())
+            .or_default()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut sum = DiagramStats::default();
+        for d in self.diagrams.values() {
+            sum.references += d.references;
+            sum.cache_hits += d.cache_hits;
+            sum.cache_misses += d.cache_misses;
+            sum.exports += d.exports;
+            sum.failures += d.failures;
+        }
+
+        let mut s = format!(
+            "drawio: {} diagrams, {} references, {} cache hits, {} cache misses, \
+             {} exports, {} failures in {:.1}ms",
+            self.diagrams.len(),
+            sum.references,
+            sum.cache_hits,
+            sum.cache_misses,
+            sum.exports,
+            sum.failures,
+            self.total_ms
+        );
+
+        // slowest first, that is what people are looking for.
+        let mut slowest: Vec<_> = self.diagrams.iter().collect();
+        slowest.sort_by(|a, b| b.1.elapsed_ms.total_cmp(&a.1.elapsed_ms));
+        for (path, d) in slowest {
+            s += &format!(
+                "\n  {:>10.1}ms  {} ({} refs, {} exports, {} failures)",
+                d.elapsed_ms, path, d.references, d.exports, d.failures
+            );
+        }
+        s
+    }
+
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_summary_test() {
+        let mut stats = BuildStats::default();
+        stats.diagram("src/a.drawio").references += 2;
+        stats.diagram("src/a.drawio").cache_hits += 1;
+        stats.diagram("src/a.drawio").add_time(Duration::from_millis(5));
+        stats.diagram("src/b.drawio").failures += 1;
+        stats.diagram("src/b.drawio").add_time(Duration::from_millis(50));
+
+        let summary = stats.summary();
+        assert!(summary.starts_with("drawio: 2 diagrams, 2 references, 1 cache hits"));
+        // slowest diagram is listed first.
+        assert!(summary.find("src/b.drawio").unwrap() < summary.find("src/a.drawio").unwrap());
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let out = temp_dir.path().join("out").join("stats.json");
+        stats.write_json(&out).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(out).unwrap()).unwrap();
+        assert_eq!(json["diagrams"]["src/a.drawio"]["references"], 2);
+    }
+}
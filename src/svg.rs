@@ -0,0 +1,636 @@
+use regex::{Captures, Regex};
+
+use std::collections::{HashMap, HashSet};
+
+use crate::config::{Config, Source, Theme};
+use crate::link::DiagramOptions;
+use crate::mxfile::Rect;
+
+// post processing of exported svgs, applied every time a diagram is
+// inlined so changing an option does not require a re-export.
+
+// colours used for the dark variant when theme = "light-dark".
+const DARK_FOREGROUND: &str = "#e6e6e6";
+const DARK_BACKGROUND: &str = "#1e1e1e";
+
+// mdbook themes with a dark background.
+const DARK_THEMES: [&str; 3] = ["coal", "navy", "ayu"];
+
+/// calls `f` with the name and attributes of every start tag in the svg,
+/// writing the tag back out with whatever attributes `f` leaves behind.
+pub fn rewrite_tags<F>(svg: &str, mut f: F) -> String
+where
+    F: FnMut(&str, &mut Vec<(String, String)>),
+{
+    let tag_regex =
+        Regex::new(r#"<([A-Za-z][\w:.-]*)((?:\s+[\w:.-]+\s*=\s*(?:"[^"]*"|'[^']*'))*)\s*(/?)>"#)
+            .unwrap();
+
+    tag_regex
+        .replace_all(svg, |caps: &Captures| {
+            let name = &caps[1];
+            let mut attrs = parse_attrs(&caps[2]);
+
+            f(name, &mut attrs);
+
+            let mut tag = format!("<{}", name);
+            for (key, value) in attrs.iter() {
+                if value.contains('"') {
+                    tag += &format!(" {}='{}'", key, value);
+                } else {
+                    tag += &format!(" {}=\"{}\"", key, value);
+                }
+            }
+            tag += &caps[3];
+            tag += ">";
+            tag
+        })
+        .to_string()
+}
+
+/// splits `a="1" b='2'` into name/value pairs, values are left escaped.
+pub fn parse_attrs(text: &str) -> Vec<(String, String)> {
+    let attr_regex = Regex::new(r#"([\w:.-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    attr_regex
+        .captures_iter(text)
+        .map(|a| {
+            let value = a.get(2).or_else(|| a.get(3)).unwrap().as_str();
+            (a[1].to_string(), value.to_string())
+        })
+        .collect()
+}
+
+pub fn get_attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+pub fn set_attr(attrs: &mut Vec<(String, String)>, name: &str, value: &str) {
+    match attrs.iter_mut().find(|(k, _)| k == name) {
+        Some(attr) => attr.1 = value.to_string(),
+        None => attrs.push((name.to_string(), value.to_string())),
+    }
+}
+
+/// appends css declarations to the style attribute of a tag.
+pub fn add_style(attrs: &mut Vec<(String, String)>, declarations: &str) {
+    let style = match get_attr(attrs, "style") {
+        Some(s) if !s.trim().is_empty() => {
+            format!("{}; {}", s.trim().trim_end_matches(';'), declarations)
+        }
+        _ => declarations.to_string(),
+    };
+    set_attr(attrs, "style", &style);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Swatch {
+    Black,
+    White,
+}
+
+// draw.io writes the same colour in several notations.
+fn swatch(value: &str) -> Option<Swatch> {
+    let v: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    match v.as_str() {
+        "#000" | "#000000" | "black" | "rgb(0,0,0)" => Some(Swatch::Black),
+        "#fff" | "#ffffff" | "white" | "rgb(255,255,255)" => Some(Swatch::White),
+        _ => None,
+    }
+}
+
+// replaces black and white in the presentation attributes and inline styles.
+// presentation attributes can't hold css variables so those are moved
+// into the style attribute.
+fn recolour(svg: &str, foreground: &str, background: &str) -> String {
+    let pick = |s: Swatch| match s {
+        Swatch::Black => foreground,
+        Swatch::White => background,
+    };
+    let decl_regex = Regex::new(
+        r"(?i)(^|;)(\s*)(color|background-color|border-color|fill|stroke|stop-color)(\s*:\s*)([^;]+)",
+    )
+    .unwrap();
+
+    rewrite_tags(svg, |_, attrs| {
+        let mut moved = vec![];
+        attrs.retain(|(key, value)| {
+            if !matches!(key.as_str(), "fill" | "stroke" | "stop-color") {
+                return true;
+            }
+            match swatch(value) {
+                Some(s) => {
+                    moved.push(format!("{}: {}", key, pick(s)));
+                    false
+                }
+                None => true,
+            }
+        });
+
+        if let Some(style) = get_attr(attrs, "style") {
+            let style = decl_regex
+                .replace_all(style, |caps: &Captures| match swatch(&caps[5]) {
+                    Some(s) => format!(
+                        "{}{}{}{}{}",
+                        &caps[1],
+                        &caps[2],
+                        &caps[3],
+                        &caps[4],
+                        pick(s)
+                    ),
+                    None => caps[0].to_string(),
+                })
+                .to_string();
+            set_attr(attrs, "style", &style);
+        }
+        if !moved.is_empty() {
+            add_style(attrs, &moved.join("; "));
+        }
+    })
+}
+
+/// css switching between the light and dark variants, needed once per page.
+pub fn theme_style() -> String {
+    let dark: Vec<String> = DARK_THEMES
+        .iter()
+        .map(|t| format!("html.{} .drawio-dark", t))
+        .collect();
+    let light: Vec<String> = DARK_THEMES
+        .iter()
+        .map(|t| format!("html.{} .drawio-light", t))
+        .collect();
+    format!(
+        "<style>.drawio-dark {{ display: none; }} {} {{ display: block; }} {} {{ display: none; }}</style>\n",
+        dark.join(", "),
+        light.join(", ")
+    )
+}
+
+/// the document from its root element on, past the xml prolog, doctype
+/// and comments.
+pub fn skip_prolog(text: &str) -> &str {
+    // the prolog, doctype (with an optional internal subset) or a comment.
+    let leading =
+        Regex::new(r"^(?s)\s*(<\?.*?\?>|<!DOCTYPE[^\[>]*(\[.*?\])?\s*>|<!--.*?-->)").unwrap();
+    let mut rest = text.trim_start_matches('\u{feff}');
+    while let Some(m) = leading.find(rest) {
+        rest = &rest[m.end()..];
+    }
+    rest.trim()
+}
+
+/// drops anything in front of the root `<svg>` element, the xml prolog,
+/// doctype and comments the exporter writes. None if there is no svg.
+pub fn strip_prolog(text: &str) -> Option<String> {
+    let rest = skip_prolog(text);
+    let root = Regex::new(r"^<svg[\s>]").unwrap();
+    if root.is_match(rest) {
+        Some(rest.to_string())
+    } else {
+        None
+    }
+}
+
+/// prefixes every id in the svg along with the references to it, so
+/// several diagrams inlined into one page don't pick up each other's
+/// markers, gradients and clip paths.
+pub fn namespace_ids(svg: &str, prefix: &str) -> String {
+    let ids: HashSet<String> = ids(svg).into_iter().collect();
+    rename_ids(svg, |id| ids.contains(id).then(|| format!("{}{}", prefix, id)))
+}
+
+/// renames the ids to id0, id1, ... in the order they appear. draw.io
+/// picks some of them at random on every export.
+pub fn renumber_ids(svg: &str) -> String {
+    let ids: HashMap<String, String> = ids(svg)
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| (id, format!("id{}", i)))
+        .collect();
+    rename_ids(svg, |id| ids.get(id).cloned())
+}
+
+// the ids in the svg, in order and without repeats.
+fn ids(svg: &str) -> Vec<String> {
+    let id_regex = Regex::new(r#"\sid\s*=\s*["']([^"']+)["']"#).unwrap();
+    let mut ids: Vec<String> = vec![];
+    for caps in id_regex.captures_iter(svg) {
+        if !ids.iter().any(|id| id == &caps[1]) {
+            ids.push(caps[1].to_string());
+        }
+    }
+    ids
+}
+
+// renames ids and the references to them, `rename` gives None for names
+// that aren't ids of the svg.
+fn rename_ids<F>(svg: &str, rename: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let url_regex = Regex::new(r#"url\(\s*['"]?#([^)'"]+)['"]?\s*\)"#).unwrap();
+    rewrite_tags(svg, |_, attrs| {
+        for (key, value) in attrs.iter_mut() {
+            if key == "id" {
+                if let Some(id) = rename(value) {
+                    *value = id;
+                }
+            } else if key == "href" || key == "xlink:href" {
+                if let Some(id) = value.strip_prefix('#').and_then(&rename) {
+                    *value = format!("#{}", id);
+                }
+            } else if value.contains("url(") {
+                *value = url_regex
+                    .replace_all(value, |caps: &Captures| match rename(&caps[1]) {
+                        Some(id) => format!("url(#{})", id),
+                        None => caps[0].to_string(),
+                    })
+                    .to_string();
+            }
+        }
+    })
+}
+
+/// makes the export of an unchanged diagram the same byte for byte: the
+/// host, app version and save time draw.io puts in the embedded diagram are
+/// dropped and the ids renumbered.
+pub fn normalize(svg: &str) -> String {
+    let mxfile_regex = Regex::new(r"^&lt;mxfile\b.*?&gt;").unwrap();
+    let volatile_regex = Regex::new(r"\s(?:host|agent|modified|etag|version)=&quot;.*?&quot;").unwrap();
+    let mut root = true;
+    let svg = rewrite_tags(svg, |name, attrs| {
+        if name != "svg" || !root {
+            return;
+        }
+        root = false;
+
+        for (key, value) in attrs.iter_mut() {
+            if key == "content" {
+                *value = mxfile_regex
+                    .replace(value, |caps: &Captures| volatile_regex.replace_all(&caps[0], "").to_string())
+                    .to_string();
+            }
+        }
+    });
+    renumber_ids(&svg)
+}
+
+// "421px" -> 421.0
+fn parse_length(value: &str) -> Option<f64> {
+    value.trim().trim_end_matches("px").parse().ok()
+}
+
+/// lets the svg scale down with the content width instead of overflowing.
+/// the exported size is kept as the max-width so small diagrams are not
+/// blown up.
+pub fn make_responsive(svg: &str) -> String {
+    let mut root = true;
+    rewrite_tags(svg, |name, attrs| {
+        if name != "svg" || !root {
+            return;
+        }
+        root = false;
+
+        let width = get_attr(attrs, "width").and_then(parse_length);
+        let height = get_attr(attrs, "height").and_then(parse_length);
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) => (w, h),
+            // percentages or no size at all, nothing to go on.
+            _ => return,
+        };
+        if get_attr(attrs, "viewBox").is_none() {
+            set_attr(attrs, "viewBox", &format!("0 0 {} {}", width, height));
+        }
+        attrs.retain(|(k, _)| k != "width" && k != "height");
+        set_attr(attrs, "width", "100%");
+        // draw.io writes its own max-width/max-height, the last one wins.
+        add_style(attrs, &format!("max-width: {}px; height: auto", width));
+    })
+}
+
+/// sizes and places the svg as a link asks for. given only one side the
+/// other follows from the viewBox, it still shrinks to fit `max-width`
+/// (the content width by default).
+pub fn set_size(svg: &str, options: &DiagramOptions) -> String {
+    if !options.sized() && options.align.is_none() {
+        return svg.to_string();
+    }
+    let mut root = true;
+    rewrite_tags(svg, |name, attrs| {
+        if name != "svg" || !root {
+            return;
+        }
+        root = false;
+
+        if options.sized() {
+            let natural = match (
+                get_attr(attrs, "width").and_then(parse_length),
+                get_attr(attrs, "height").and_then(parse_length),
+            ) {
+                (Some(w), Some(h)) => Some((w, h)),
+                _ => None,
+            };
+            if let (None, Some((w, h))) = (get_attr(attrs, "viewBox"), natural) {
+                set_attr(attrs, "viewBox", &format!("0 0 {} {}", w, h));
+            }
+            let (width, height) = options.size(natural);
+            // the height follows the width, which keeps make_responsive
+            // from replacing it too.
+            match (width.or(natural.map(|n| n.0)), height) {
+                (Some(w), _) => {
+                    attrs.retain(|(k, _)| k != "width" && k != "height");
+                    set_attr(attrs, "width", &w.to_string());
+                    add_style(attrs, "height: auto");
+                }
+                (None, Some(h)) => {
+                    attrs.retain(|(k, _)| k != "width" && k != "height");
+                    set_attr(attrs, "height", &h.to_string());
+                    add_style(attrs, "width: auto");
+                }
+                _ => {},
+            }
+            let max_width = options.max_width.as_deref().unwrap_or("100%");
+            add_style(attrs, &format!("max-width: {}", max_width));
+        }
+        if let Some(align) = options.align {
+            add_style(attrs, align.style());
+        }
+    })
+}
+
+/// the size of a png from its header.
+pub fn png_size(png: &[u8]) -> Option<(f64, f64)> {
+    if png.len() < 24 || &png[12..16] != b"IHDR" {
+        return None;
+    }
+    let number = |at: usize| u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]) as f64;
+    Some((number(16), number(20)))
+}
+
+/// crops the svg to `region`, in page coordinates. `page` is the bounds of
+/// everything on the page, which the exporter puts at the viewBox origin.
+pub fn crop(svg: &str, region: &Rect, page: &Rect) -> String {
+    let mut root = true;
+    rewrite_tags(svg, |name, attrs| {
+        if name != "svg" || !root {
+            return;
+        }
+        root = false;
+
+        let origin: Vec<f64> = get_attr(attrs, "viewBox")
+            .unwrap_or("0 0")
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        let (vx, vy) = match origin[..] {
+            [x, y, ..] => (x, y),
+            _ => (0.0, 0.0),
+        };
+        let view_box = format!(
+            "{} {} {} {}",
+            vx + region.x - page.x,
+            vy + region.y - page.y,
+            region.width,
+            region.height
+        );
+        set_attr(attrs, "viewBox", &view_box);
+        set_attr(attrs, "width", &format!("{}px", region.width));
+        set_attr(attrs, "height", &format!("{}px", region.height));
+    })
+}
+
+/// rewrites the target of every link in the svg, `resolve` gets the
+/// current target and returns the new one or None to leave it alone.
+pub fn rewrite_links<F>(svg: &str, mut resolve: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    rewrite_tags(svg, |name, attrs| {
+        if name != "a" {
+            return;
+        }
+        let key = if get_attr(attrs, "xlink:href").is_some() {
+            "xlink:href"
+        } else {
+            "href"
+        };
+        let target = match get_attr(attrs, key).and_then(&mut resolve) {
+            Some(t) => t,
+            None => return,
+        };
+        set_attr(attrs, key, &target);
+        // links within the book open in the same tab.
+        attrs.retain(|(k, _)| k != "target");
+    })
+}
+
+/// drops the diagram draw.io embeds in the svg's `content` attribute.
+pub fn strip_source(svg: &str) -> String {
+    let mut root = true;
+    rewrite_tags(svg, |name, attrs| {
+        if name == "svg" && root {
+            root = false;
+            attrs.retain(|(k, _)| k != "content");
+        }
+    })
+}
+
+/// removes comments and the indentation between tags. other whitespace is
+/// kept, between text runs it is a visible space.
+pub fn minify(svg: &str) -> String {
+    let comments = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let between = Regex::new(r">[ \t]*(?:\r?\n[ \t]*)+<").unwrap();
+    let svg = comments.replace_all(svg, "");
+    between.replace_all(&svg, "><").trim().to_string()
+}
+
+fn light_dark(light: &str, dark: &str) -> String {
+    format!(
+        "<div class=\"drawio-light\">{}</div><div class=\"drawio-dark\">{}</div>",
+        light, dark
+    )
+}
+
+/// makes the svg follow the mdbook theme.
+pub fn apply_theme(svg: &str, theme: Theme) -> String {
+    match theme {
+        Theme::None => svg.to_string(),
+        Theme::CssVariables => recolour(svg, "var(--fg)", "var(--bg)"),
+        Theme::LightDark => light_dark(svg, &recolour(svg, DARK_FOREGROUND, DARK_BACKGROUND)),
+    }
+}
+
+/// runs an exported svg through every post processing step before
+/// it is inlined, in `theme` rather than the book's. `id_prefix` has to be
+/// unique within the book, print.html shows every chapter at once.
+pub fn process(svg: &str, config: &Config, theme: Theme, id_prefix: &str) -> String {
+    let svg = match config.source {
+        Source::Keep => svg.to_string(),
+        Source::Strip => strip_source(svg),
+    };
+    let svg = if config.responsive {
+        make_responsive(&svg)
+    } else {
+        svg
+    };
+
+    let svg = match theme {
+        // the light and dark copies each need their own ids.
+        Theme::LightDark => light_dark(
+            &namespace_ids(&svg, id_prefix),
+            &namespace_ids(
+                &recolour(&svg, DARK_FOREGROUND, DARK_BACKGROUND),
+                &format!("{}dark-", id_prefix),
+            ),
+        ),
+        theme => namespace_ids(&apply_theme(&svg, theme), id_prefix),
+    };
+
+    if config.minify {
+        minify(&svg)
+    } else {
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" style="background-color: rgb(255, 255, 255);"><rect fill="#ffffff" stroke="rgb(0, 0, 0)" pointer-events="all"/><div style="color: rgb(0, 0, 0); font-size: 12px">hi</div><rect fill="#dae8fc"/></svg>"##;
+
+    #[test]
+    fn css_variables_test() {
+        let themed = apply_theme(SVG, Theme::CssVariables);
+        assert_eq!(
+            themed,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" style="background-color: var(--bg);"><rect pointer-events="all" style="fill: var(--bg); stroke: var(--fg)"/><div style="color: var(--fg); font-size: 12px">hi</div><rect fill="#dae8fc"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn light_dark_test() {
+        let themed = apply_theme(SVG, Theme::LightDark);
+        assert!(themed.starts_with(&format!("<div class=\"drawio-light\">{}</div>", SVG)));
+        assert!(themed.contains("<div class=\"drawio-dark\"><svg"));
+        assert!(themed.contains("fill: #1e1e1e; stroke: #e6e6e6"));
+        assert!(theme_style().contains("html.navy .drawio-dark"));
+    }
+
+    #[test]
+    fn strip_prolog_test() {
+        let exported = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<!-- <svg> in a comment -->
+<svg
+  width="10px"><g/></svg>
+"#;
+        assert_eq!(
+            strip_prolog(exported).unwrap(),
+            "<svg\n  width=\"10px\"><g/></svg>"
+        );
+        assert_eq!(strip_prolog("<html></html>"), None);
+    }
+
+    #[test]
+    fn normalize_test() {
+        let svg = r##"<svg content="&lt;mxfile host=&quot;Electron&quot; modified=&quot;2024-05-01T10:00:00.000Z&quot; agent=&quot;Mozilla/5.0 (X11)&quot; etag=&quot;x1Y2&quot; version=&quot;24.2.5&quot; type=&quot;device&quot;&gt;&lt;diagram id=&quot;p1&quot;/&gt;&lt;/mxfile&gt;"><defs><clipPath id="mx-clip-Rk3"/><marker id="m"/></defs><g clip-path="url(#mx-clip-Rk3)"><path marker-end="url(#m)"/></g><g id="m"/></svg>"##;
+        assert_eq!(
+            normalize(svg),
+            r##"<svg content="&lt;mxfile type=&quot;device&quot;&gt;&lt;diagram id=&quot;p1&quot;/&gt;&lt;/mxfile&gt;"><defs><clipPath id="id0"/><marker id="id1"/></defs><g clip-path="url(#id0)"><path marker-end="url(#id1)"/></g><g id="id1"/></svg>"##
+        );
+        assert_eq!(normalize(svg), normalize(&svg.replace("Rk3", "Zq9").replace("24.2.5", "24.7.0")));
+    }
+
+    #[test]
+    fn namespace_ids_test() {
+        let svg = r##"<svg><defs><marker id="arrow"/><linearGradient id="g1"/></defs><path marker-end="url(#arrow)" fill="url('#g1')" style="clip-path: url(#other)"/><use xlink:href="#g1"/></svg>"##;
+        assert_eq!(
+            namespace_ids(svg, "d1-"),
+            r##"<svg><defs><marker id="d1-arrow"/><linearGradient id="d1-g1"/></defs><path marker-end="url(#d1-arrow)" fill="url(#d1-g1)" style="clip-path: url(#other)"/><use xlink:href="#d1-g1"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn make_responsive_test() {
+        let svg = r#"<svg width="421px" height="281px" viewBox="-0.5 -0.5 421 281" style="background-color: white;"><svg width="5" height="5"/></svg>"#;
+        assert_eq!(
+            make_responsive(svg),
+            r#"<svg viewBox="-0.5 -0.5 421 281" style="background-color: white; max-width: 421px; height: auto" width="100%"><svg width="5" height="5"/></svg>"#
+        );
+        assert_eq!(
+            make_responsive(r#"<svg width="20" height="10"></svg>"#),
+            r#"<svg viewBox="0 0 20 10" width="100%" style="max-width: 20px; height: auto"></svg>"#
+        );
+    }
+
+    #[test]
+    fn set_size_test() {
+        let svg = r#"<svg width="400px" height="200px" style="background-color: white;"><svg width="5" height="5"/></svg>"#;
+        let options = DiagramOptions::parse("scale=50%&max-width=80%&align=center");
+        // responsive leaves a sized svg alone.
+        assert_eq!(
+            make_responsive(&set_size(svg, &options)),
+            r#"<svg style="background-color: white; height: auto; max-width: 80%; display: block; margin-left: auto; margin-right: auto" viewBox="0 0 400 200" width="200"><svg width="5" height="5"/></svg>"#
+        );
+        let options = DiagramOptions::parse("height=100");
+        assert_eq!(
+            set_size(svg, &options),
+            r#"<svg style="background-color: white; height: auto; max-width: 100%" viewBox="0 0 400 200" width="200"><svg width="5" height="5"/></svg>"#
+        );
+        assert_eq!(set_size(svg, &DiagramOptions::default()), svg);
+
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend([0, 0, 1, 44, 0, 0, 0, 150]);
+        assert_eq!(png_size(&png), Some((300.0, 150.0)));
+    }
+
+    #[test]
+    fn crop_test() {
+        let svg = r#"<svg width="421px" height="281px" viewBox="-0.5 -0.5 421 281"><svg/></svg>"#;
+        let region = Rect::parse("100,50,40,20").unwrap();
+        let page = Rect::parse("20,10,421,281").unwrap();
+        assert_eq!(
+            crop(svg, &region, &page),
+            r#"<svg width="40px" height="20px" viewBox="79.5 39.5 40 20"><svg/></svg>"#
+        );
+    }
+
+    #[test]
+    fn rewrite_links_test() {
+        let svg = r#"<svg><a xlink:href="db.md" target="_blank"><g/></a><a href="https://example.com" target="_blank"/></svg>"#;
+        let rewritten = rewrite_links(svg, |href| {
+            href.strip_suffix(".md").map(|p| format!("{}.html", p))
+        });
+        assert_eq!(
+            rewritten,
+            r#"<svg><a xlink:href="db.html"><g/></a><a href="https://example.com" target="_blank"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn strip_source_test() {
+        assert_eq!(
+            strip_source(r#"<svg width="10" content="&lt;mxfile&gt;"><svg content="x"/></svg>"#),
+            r#"<svg width="10"><svg content="x"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn minify_test() {
+        assert_eq!(
+            minify("<svg>\n  <!-- note -->\n  <g>\n    <text>a b</text>\n  </g>\n</svg>\n"),
+            "<svg><g><text>a b</text></g></svg>"
+        );
+        assert_eq!(
+            minify("<text>\n  <tspan>a</tspan> <tspan>b</tspan>\n</text>"),
+            "<text><tspan>a</tspan> <tspan>b</tspan></text>"
+        );
+    }
+}
@@ -1,5 +1,9 @@
+use mdbook::errors::Result;
+use path_clean::PathClean;
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
 
-fn absolute_path(path: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+pub fn absolute_path(path: impl AsRef<Path>) -> std::io::Result<PathBuf> {
     let path = path.as_ref();
 
     let absolute_path = if path.is_absolute() {
@@ -13,7 +17,7 @@ fn absolute_path(path: impl AsRef<Path>) -> std::io::Result<PathBuf> {
 
 /// Computes a relative, if possible, from path to start.
 /// this attempts to be similar in functionality to that of pythons os.path.relpath.
-fn relative_path(path: impl AsRef<Path>, start: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+pub fn relative_path(path: impl AsRef<Path>, start: impl AsRef<Path>) -> std::io::Result<PathBuf> {
     let start_p = absolute_path(start)?;
     let path_p = absolute_path(path)?;
     let start_p_str = start_p.to_str().unwrap();
@@ -57,7 +61,7 @@ fn relative_path(path: impl AsRef<Path>, start: impl AsRef<Path>) -> std::io::Re
         uncommon_parts.push("..".to_string());
     }
 
-    while let Some(start_n) = start_p_split.next() {
+    for start_n in start_p_split {
         uncommon_parts.push("..".to_string());
     }
 
@@ -65,7 +69,7 @@ fn relative_path(path: impl AsRef<Path>, start: impl AsRef<Path>) -> std::io::Re
         uncommon_parts.push(n.to_string());
     }
 
-    while let Some(path_n) = path_p_split.next() {
+    for path_n in path_p_split {
         uncommon_parts.push(path_n.to_string());
     }
 
@@ -113,6 +117,30 @@ fn replace_links(content: &str) -> Result<(Vec<String>, String)> {
 }
 
 
+/// lowercases and replaces anything that isn't alphanumeric with '-',
+/// for use in html ids.
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// 64 bit fnv-1a as hex, stable between builds unlike the std hasher so
+/// it can be used in file names.
+pub fn hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
 
 #[cfg(test)]
 mod tests {
@@ -151,6 +179,18 @@ mod tests {
         assert_eq!(relative_path(path, cur_p).unwrap(), expected_result);
     }
 
+    #[test]
+    fn slug_test() {
+        assert_eq!(slug("Page-1"), "page-1");
+        assert_eq!(slug(" Current & Target "), "current-target");
+    }
+
+    #[test]
+    fn hash_test() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+
     #[test]
     fn test_link_extraction() {
         let content = r#"
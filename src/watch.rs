@@ -0,0 +1,122 @@
+use mdbook::config::Config as BookConfig;
+use mdbook::errors::Result;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use path_clean::PathClean;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::link;
+use crate::DrawIo;
+
+// `mdbook-drawio watch`, run next to `mdbook serve`. mdbook only watches
+// src, so diagrams kept elsewhere never trigger a rebuild. this watches the
+// diagram directories too, exports changed diagrams into the cache and then
+// rewrites the chapters showing them so serve rebuilds (from the cache) and
+// reloads the page.
+
+/// watches the book at `book_dir` until killed.
+pub fn watch<P: AsRef<Path>>(book_dir: P) -> Result<()> {
+    // the cache, like the preprocessor, works relative to the book root.
+    std::env::set_current_dir(book_dir)?;
+    let book = BookConfig::from_disk("book.toml")?;
+    let config = Config::from_book(&book)?;
+    let src_dir = book.book.src.clone();
+    let drawio = DrawIo::new(".drawio-cache");
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, Duration::from_millis(500))?;
+    let mut dirs = vec![src_dir.clone()];
+    dirs.extend(config.watch_dirs.iter().filter(|d| **d != src_dir).cloned());
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        log::info!("Watching {} for diagram changes", dir.display());
+    }
+
+    let root = std::env::current_dir()?;
+    for event in rx {
+        let path = match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Rename(_, path) => path,
+            _ => continue,
+        };
+        let path = path.strip_prefix(&root).map(Path::to_path_buf).unwrap_or(path);
+        if !is_diagram(&path, &config.extensions) || path.starts_with(".drawio-cache") {
+            continue;
+        }
+
+        log::info!("{} changed", path.display());
+        // editable images are shown without an export, or extracted into
+        // a new file when given options.
+        if !link::is_image(&path) {
+            match drawio.export_pages(&path, link::Format::Svg) {
+                Ok(exported) => log::info!("Exported {} changed pages of {}", exported, path.display()),
+                Err(f) => {
+                    log::error!("Failed to export {}: {}", path.display(), f);
+                    continue;
+                }
+            }
+        }
+        for chapter in referencing_chapters(&src_dir, &path, &config.extensions) {
+            log::info!("Reloading {}", chapter.display());
+            // mdbook only rebuilds on writes, a new mtime isn't enough.
+            if let Err(e) = std::fs::read(&chapter).and_then(|c| std::fs::write(&chapter, c)) {
+                log::error!("Failed to touch {}: {}", chapter.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_diagram(path: &Path, extensions: &[String]) -> bool {
+    link::is_image(path)
+        || path
+            .extension()
+            .is_some_and(|e| extensions.iter().any(|x| x.trim_start_matches('.') == e))
+}
+
+/// the chapters in `src_dir` linking to `diagram`, both relative to the book
+/// root.
+fn referencing_chapters(src_dir: &Path, diagram: &Path, extensions: &[String]) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(src_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x == "md"))
+        .filter(|e| {
+            let content = std::fs::read_to_string(e.path()).unwrap_or_default();
+            let chapter_dir = e.path().parent().unwrap_or(src_dir);
+            link::find_links(&content, extensions, chapter_dir)
+                .into_iter()
+                .chain(link::find_directives(&content).into_iter().filter_map(|d| d.ok()))
+                .any(|l| chapter_dir.join(&l.path).clean() == diagram)
+        })
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn referencing_chapters_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("guide")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("diagrams")).unwrap();
+        std::fs::write(temp_dir.path().join("diagrams/arch.drawio"), "<mxfile/>").unwrap();
+        std::fs::write(src_dir.join("intro.md"), "![a](../diagrams/arch-Net.drawio)").unwrap();
+        std::fs::write(src_dir.join("guide/setup.md"), "{{#drawio ../../diagrams/arch.drawio all}}").unwrap();
+        std::fs::write(src_dir.join("other.md"), "![b](arch.drawio)").unwrap();
+
+        let extensions = Config::default().extensions;
+        let diagram = temp_dir.path().join("diagrams/arch.drawio");
+        let mut chapters = referencing_chapters(&src_dir, &diagram, &extensions);
+        chapters.sort();
+        assert_eq!(chapters, vec![src_dir.join("guide/setup.md"), src_dir.join("intro.md")]);
+
+        assert!(is_diagram(Path::new("diagrams/arch.dio"), &extensions));
+        assert!(is_diagram(Path::new("flow.drawio.png"), &extensions));
+        assert!(!is_diagram(Path::new("src/intro.md"), &extensions));
+    }
+}